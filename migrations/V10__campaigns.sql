@@ -0,0 +1,37 @@
+CREATE TABLE IF NOT EXISTS campaigns (
+    id bigserial PRIMARY KEY,
+    guild_id text NOT NULL,
+    name text NOT NULL,
+    pack text,
+    UNIQUE (guild_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS campaign_channels (
+    channel_id text PRIMARY KEY,
+    campaign_id bigint NOT NULL REFERENCES campaigns (id) ON DELETE CASCADE
+);
+
+-- Campaign 0 is the whole guild, it is used by channels not bound to any campaign
+ALTER TABLE users ADD COLUMN IF NOT EXISTS campaign_id bigint NOT NULL DEFAULT 0;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_pkey;
+ALTER TABLE users ADD PRIMARY KEY (id, guild_id, campaign_id);
+
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS campaign_id bigint NOT NULL DEFAULT 0;
+ALTER TABLE guilds DROP CONSTRAINT IF EXISTS guilds_pkey;
+ALTER TABLE guilds ADD PRIMARY KEY (id, campaign_id);
+
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS campaign_id bigint NOT NULL DEFAULT 0;
+ALTER TABLE guild_settings DROP CONSTRAINT IF EXISTS guild_settings_pkey;
+ALTER TABLE guild_settings ADD PRIMARY KEY (guild_id, campaign_id);
+
+ALTER TABLE rituals ADD COLUMN IF NOT EXISTS campaign_id bigint NOT NULL DEFAULT 0;
+ALTER TABLE rituals DROP CONSTRAINT IF EXISTS rituals_pkey;
+ALTER TABLE rituals ADD PRIMARY KEY (guild_id, campaign_id);
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS campaign_id bigint NOT NULL DEFAULT 0;
+DROP INDEX IF EXISTS sessions_guild_id_idx;
+CREATE INDEX IF NOT EXISTS sessions_guild_id_idx ON sessions (guild_id, campaign_id, id);
+
+ALTER TABLE signs ADD COLUMN IF NOT EXISTS campaign_id bigint NOT NULL DEFAULT 0;
+DROP INDEX IF EXISTS signs_guild_id_created_at_idx;
+CREATE INDEX IF NOT EXISTS signs_guild_id_created_at_idx ON signs (guild_id, campaign_id, created_at);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS characters (
+    id bigserial PRIMARY KEY,
+    guild_id text NOT NULL,
+    campaign_id bigint NOT NULL DEFAULT 0,
+    owner_id text NOT NULL,
+    name text NOT NULL,
+    shaman_power int NOT NULL,
+    UNIQUE (guild_id, campaign_id, owner_id, name)
+);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS active_character_id bigint REFERENCES characters (id) ON DELETE SET NULL;
@@ -0,0 +1,8 @@
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS power_min int NOT NULL DEFAULT 1;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS power_max int NOT NULL DEFAULT 20;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS power_baseline int NOT NULL DEFAULT 10;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS power_decay int NOT NULL DEFAULT 0;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS ranks text NOT NULL DEFAULT '0:Ученик,10:Шаман,16:Верховный шаман';
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS power_updated_at timestamp NOT NULL DEFAULT NOW();
+ALTER TABLE characters ADD COLUMN IF NOT EXISTS power_updated_at timestamp NOT NULL DEFAULT NOW();
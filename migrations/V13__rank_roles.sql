@@ -0,0 +1 @@
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS rank_roles text NOT NULL DEFAULT '';
@@ -0,0 +1,2 @@
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS modify_role_ids text NOT NULL DEFAULT '';
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS roll_role_ids text NOT NULL DEFAULT '';
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS modify_rolls (
+    id bigserial PRIMARY KEY,
+    guild_id text NOT NULL,
+    campaign_id bigint NOT NULL DEFAULT 0,
+    user_id text NOT NULL,
+    sign_row_id bigint NOT NULL,
+    d20 int NOT NULL,
+    value int NOT NULL,
+    success boolean NOT NULL,
+    critical boolean NOT NULL,
+    power_before int NOT NULL,
+    power_after int NOT NULL,
+    created_at timestamp NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS modify_rolls_user_idx ON modify_rolls (guild_id, campaign_id, user_id);
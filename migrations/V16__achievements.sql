@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS achievements (
+    guild_id text NOT NULL,
+    campaign_id bigint NOT NULL DEFAULT 0,
+    user_id text NOT NULL,
+    achievement_id text NOT NULL,
+    unlocked_at timestamp NOT NULL,
+    PRIMARY KEY (guild_id, campaign_id, user_id, achievement_id)
+);
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS webhooks (
+    id bigserial PRIMARY KEY,
+    guild_id text NOT NULL,
+    campaign_id bigint NOT NULL DEFAULT 0,
+    url text NOT NULL,
+    secret text NOT NULL,
+    UNIQUE (guild_id, campaign_id, url)
+);
+
+-- Events are kept until delivered, so they survive restarts
+CREATE TABLE IF NOT EXISTS webhook_outbox (
+    id bigserial PRIMARY KEY,
+    webhook_id bigint NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    event_type text NOT NULL,
+    payload text NOT NULL,
+    created_at timestamp NOT NULL,
+    attempts int NOT NULL DEFAULT 0,
+    next_attempt_at timestamp,
+    last_error text,
+    delivered_at timestamp
+);
+
+CREATE INDEX IF NOT EXISTS webhook_outbox_due_idx ON webhook_outbox (next_attempt_at) WHERE delivered_at IS NULL;
@@ -0,0 +1,10 @@
+-- Only hash of token is stored, token itself is shown once on creation
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id bigserial PRIMARY KEY,
+    guild_id text NOT NULL,
+    name text NOT NULL,
+    token_hash text NOT NULL UNIQUE,
+    created_by_id text NOT NULL,
+    created_at timestamp NOT NULL,
+    UNIQUE (guild_id, name)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS sign_pack text;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS timezone text;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS gm_role_id text;
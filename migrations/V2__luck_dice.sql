@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS luck_dice int NOT NULL DEFAULT 0;
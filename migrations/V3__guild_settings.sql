@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS guild_settings (
+    guild_id text PRIMARY KEY,
+    modify_roll_mode text NOT NULL DEFAULT 'normal'
+);
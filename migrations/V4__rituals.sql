@@ -0,0 +1,14 @@
+ALTER TABLE guild_settings
+    ADD COLUMN IF NOT EXISTS assist_max_helpers int NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS assist_bonus int NOT NULL DEFAULT 1,
+    ADD COLUMN IF NOT EXISTS assist_timeout_secs int NOT NULL DEFAULT 60;
+
+CREATE TABLE IF NOT EXISTS rituals (
+    guild_id text PRIMARY KEY,
+    shaman_id text NOT NULL,
+    use_luck boolean NOT NULL,
+    channel_id text NOT NULL,
+    helpers text[] NOT NULL DEFAULT '{}',
+    message_id text,
+    expires_at timestamp NOT NULL
+);
@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS signs (
+    id bigserial PRIMARY KEY,
+    guild_id text NOT NULL,
+    sign_id text NOT NULL,
+    created_at timestamp NOT NULL,
+    created_by_id text NOT NULL,
+    state text NOT NULL,
+    state_made_by_id text,
+    discarded boolean NOT NULL DEFAULT false
+);
+
+CREATE INDEX IF NOT EXISTS signs_guild_id_created_at_idx ON signs (guild_id, created_at);
+
+INSERT INTO signs (guild_id, sign_id, created_at, created_by_id, state, state_made_by_id)
+SELECT id, sign_id, sign_created_at, sign_created_by_id, sign_state, sign_state_made_by_id
+FROM guilds;
+
+ALTER TABLE guilds
+    ADD COLUMN IF NOT EXISTS next_roll text,
+    ADD COLUMN IF NOT EXISTS candidates text[];
@@ -0,0 +1,23 @@
+ALTER TABLE signs ADD COLUMN IF NOT EXISTS modifiable boolean NOT NULL DEFAULT true;
+
+ALTER TABLE guilds
+    DROP COLUMN IF EXISTS sign_id,
+    DROP COLUMN IF EXISTS sign_created_at,
+    DROP COLUMN IF EXISTS sign_created_by_id,
+    DROP COLUMN IF EXISTS sign_state,
+    DROP COLUMN IF EXISTS sign_state_made_by_id;
+
+ALTER TABLE rituals ADD COLUMN IF NOT EXISTS sign_row_id bigint;
+
+UPDATE rituals r
+SET sign_row_id = (
+    SELECT s.id
+    FROM signs s
+    WHERE s.guild_id = r.guild_id AND NOT s.discarded
+    ORDER BY s.created_at DESC, s.id DESC
+    LIMIT 1
+);
+
+DELETE FROM rituals WHERE sign_row_id IS NULL;
+
+ALTER TABLE rituals ALTER COLUMN sign_row_id SET NOT NULL;
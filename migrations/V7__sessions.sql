@@ -0,0 +1,25 @@
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS cadence text NOT NULL DEFAULT 'daily';
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id bigserial PRIMARY KEY,
+    guild_id text NOT NULL,
+    started_at timestamp NOT NULL,
+    event_id text
+);
+
+CREATE INDEX IF NOT EXISTS sessions_guild_id_idx ON sessions (guild_id, id);
+
+-- Signs rolled before cadence setting belong to daily sessions
+INSERT INTO sessions (guild_id, started_at)
+SELECT guild_id, created_at::date
+FROM signs
+GROUP BY guild_id, created_at::date;
+
+ALTER TABLE signs ADD COLUMN IF NOT EXISTS session_id bigint;
+
+UPDATE signs s
+SET session_id = ss.id
+FROM sessions ss
+WHERE ss.guild_id = s.guild_id AND ss.started_at = s.created_at::date;
+
+ALTER TABLE signs ALTER COLUMN session_id SET NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS auto_roll_channel_id text;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS auto_roll_event_name text;
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS auto_roll_event_channel_id text;
@@ -0,0 +1 @@
+ALTER TABLE signs ADD COLUMN IF NOT EXISTS hidden boolean NOT NULL DEFAULT false;
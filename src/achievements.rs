@@ -0,0 +1,142 @@
+use std::{fs, sync::OnceLock};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::db::{Dao, ModifyRoll, Scope};
+
+static DATA: OnceLock<Vec<AchievementData>> = OnceLock::new();
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AchievementData {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub trigger: Trigger
+}
+
+/**
+ * Condition checked after sign roll or modification, all set fields should match
+ */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// Player rolled sign
+    Roll {
+        #[serde(default)]
+        sign_id: Option<String>,
+        /// Player rolled at least this many signs in total
+        #[serde(default)]
+        count: Option<i64>
+    },
+    /// Player modified sign
+    Modify {
+        #[serde(default)]
+        sign_id: Option<String>,
+        #[serde(default)]
+        success: Option<bool>,
+        #[serde(default)]
+        critical: Option<bool>
+    },
+    /// Natural d20 result of modification
+    NaturalRoll {
+        value: i32
+    },
+    /// Successful modifications in a row
+    SuccessStreak {
+        count: i64
+    },
+    /// Shaman power reached after modification
+    Power {
+        min: i32
+    }
+}
+
+/**
+ * What player has just done
+ */
+pub enum Event<'a> {
+    Rolled { sign_ids: &'a [String] },
+    Modified { sign_id: &'a str, roll: &'a ModifyRoll }
+}
+
+/**
+ * Loads achievement definitions, without them no achievements are given
+ */
+pub fn load_achievements(file_path: String) -> Result<()> {
+    let data = fs::read_to_string(file_path)?;
+    let data: Vec<AchievementData> = serde_json::from_str(&data)?;
+
+    if DATA.set(data).is_err() {
+        return Err(anyhow::anyhow!("Cannot load achievements from file"));
+    }
+    Ok(())
+}
+
+pub fn list_achievements() -> &'static [AchievementData] {
+    DATA.get().map(|d| d.as_slice()).unwrap_or_default()
+}
+
+pub fn get_achievement(id: &str) -> Option<&'static AchievementData> {
+    list_achievements().iter().find(|a| a.id == id)
+}
+
+/**
+ * Checks achievements against event and saves unlocked ones
+ * Returns achievements which player unlocked just now
+ */
+pub async fn check(dao: &dyn Dao, user_id: u64, scope: Scope, event: Event<'_>) -> Result<Vec<&'static AchievementData>> {
+    let achievements = list_achievements();
+
+    if achievements.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let needs_stats = achievements.iter().any(|a| matches!(a.trigger, Trigger::Roll { count: Some(_), .. }));
+    let signs_rolled = if needs_stats && matches!(event, Event::Rolled { .. }) {
+        dao.get_user_stats(user_id, scope).await?.signs_rolled
+    } else {
+        0
+    };
+
+    let needs_streak = achievements.iter().any(|a| matches!(a.trigger, Trigger::SuccessStreak { .. }));
+    let streak = if needs_streak && matches!(event, Event::Modified { .. }) {
+        dao.get_success_streak(user_id, scope).await?
+    } else {
+        0
+    };
+
+    let matched = achievements.iter()
+        .filter(|a| match (&a.trigger, &event) {
+            (Trigger::Roll { sign_id, count }, Event::Rolled { sign_ids }) =>
+                sign_id.as_ref().is_none_or(|id| sign_ids.contains(id))
+                    && count.is_none_or(|c| signs_rolled >= c),
+            (Trigger::Modify { sign_id: expected, success, critical }, Event::Modified { sign_id, roll }) =>
+                expected.as_ref().is_none_or(|id| id == sign_id)
+                    && success.is_none_or(|s| s == roll.success)
+                    && critical.is_none_or(|c| c == roll.critical),
+            (Trigger::NaturalRoll { value }, Event::Modified { roll, .. }) => roll.d20 == *value,
+            (Trigger::SuccessStreak { count }, Event::Modified { .. }) => streak >= *count,
+            (Trigger::Power { min }, Event::Modified { roll, .. }) => roll.power_after >= *min,
+            _ => false,
+        })
+        .map(|a| a.id.clone())
+        .collect::<Vec<_>>();
+
+    if matched.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let unlocked = dao.unlock_achievements(user_id, scope, matched).await?;
+
+    Ok(unlocked.iter().filter_map(|id| get_achievement(id)).collect())
+}
+
+/**
+ * Announcement of achievements unlocked just now, empty if there are none
+ */
+pub fn render_unlocked(unlocked: &[&AchievementData]) -> String {
+    unlocked.iter()
+        .map(|a| format!("🏆 Получено достижение «{}»: {}\n", a.name, a.description))
+        .collect()
+}
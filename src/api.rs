@@ -0,0 +1,242 @@
+use std::{collections::HashMap, convert::Infallible, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use anyhow::Result;
+use futures_util::stream;
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Full, StreamBody};
+use hyper::{body::{Bytes, Frame}, Method, Request, Response, StatusCode};
+use log::warn;
+use querystring::querify;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+
+use crate::{db::{Dao, GuildSettings, Scope, SignInfo, SignState}, events, power, signs::{self, Pack}};
+
+const DEFAULT_HISTORY_LIMIT: i64 = 20;
+const MAX_HISTORY_LIMIT: i64 = 100;
+/// Comment sent to idle event streams, so proxies do not close them
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+pub type Body = UnsyncBoxBody<Bytes, Infallible>;
+
+/**
+ * Sign as it is shown to tools outside of Discord
+ * Discord ids are strings, so they are not rounded by JSON parsers
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct SignView {
+    pub row_id: i64,
+    pub id: String,
+    pub name: String,
+    pub created_by_user_id: String,
+    pub created_at: u64,
+    pub state: &'static str,
+    pub state_made_by_user_id: Option<String>,
+    pub modifiable: bool
+}
+
+impl SignView {
+    /**
+     * View of sign with name from pack of its scope
+     */
+    pub fn new(pack: &Pack, sign: &SignInfo) -> Self {
+        let (state, state_made_by) = match sign.state {
+            SignState::Created => ("created", None),
+            SignState::Success { by_user_id } => ("success", Some(by_user_id)),
+            SignState::Failed { by_user_id } => ("failed", Some(by_user_id)),
+            SignState::CriticalSuccess { by_user_id } => ("critical_success", Some(by_user_id)),
+            SignState::CriticalFailure { by_user_id } => ("critical_failure", Some(by_user_id)),
+        };
+
+        SignView {
+            row_id: sign.row_id,
+            id: sign.id.clone(),
+            name: pack.get_name(&sign.id),
+            created_by_user_id: sign.created_by_user_id.to_string(),
+            created_at: unix_secs(sign.created_at),
+            state,
+            state_made_by_user_id: state_made_by.map(|id| id.to_string()),
+            modifiable: sign.modifiable
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CurrentSigns {
+    guild_id: String,
+    campaign_id: i64,
+    signs: Vec<SignView>
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryView {
+    #[serde(flatten)]
+    sign: SignView,
+    discarded: bool
+}
+
+#[derive(Debug, Serialize)]
+struct UserView {
+    user_id: String,
+    shaman_power: i32,
+    rank: Option<String>,
+    luck_dice: i32,
+    character: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorView<'a> {
+    error: &'a str
+}
+
+/**
+ * Read-only API for overlays and tools, authorized with `Authorization: Bearer {token}` of guild
+ * Campaign is chosen with `campaign_id` query parameter, hidden signs are never shown
+ *
+ * `GET /api/guilds/{guild_id}/sign`
+ * `GET /api/guilds/{guild_id}/history?limit={limit}`
+ * `GET /api/guilds/{guild_id}/users/{user_id}`
+ * `GET /api/guilds/{guild_id}/events` - server-sent events about created and modified signs
+ */
+pub async fn handle<B>(dao: &dyn Dao, req: &Request<B>) -> Result<Response<Body>> {
+    if req.method() != Method::GET {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+    }
+
+    let segments = req.uri().path().trim_matches('/').split('/').collect::<Vec<_>>();
+    let ["api", "guilds", guild_id, route @ ..] = segments.as_slice() else {
+        return error(StatusCode::NOT_FOUND, "not found");
+    };
+
+    let Ok(guild_id) = guild_id.parse::<u64>() else {
+        return error(StatusCode::NOT_FOUND, "not found");
+    };
+
+    let token = req.headers().get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    let authorized = match token {
+        Some(token) => dao.check_api_token(guild_id, hash_token(token)).await?,
+        None => false,
+    };
+
+    if !authorized {
+        return error(StatusCode::UNAUTHORIZED, "invalid token");
+    }
+
+    let query: HashMap<&str, &str> = querify(req.uri().query().unwrap_or("")).into_iter().collect();
+    let scope = match query.get("campaign_id").map(|c| c.parse()) {
+        Some(Ok(campaign_id)) => Scope::campaign(guild_id, campaign_id),
+        Some(Err(_)) => return error(StatusCode::BAD_REQUEST, "invalid campaign_id"),
+        None => Scope::guild(guild_id),
+    };
+
+    match route {
+        ["sign"] => {
+            let signs = dao.get_guild_info(scope).await?
+                .map(|g| g.signs)
+                .unwrap_or_default();
+            let pack = signs::scope_pack(dao, scope).await?;
+
+            json(StatusCode::OK, &CurrentSigns {
+                guild_id: guild_id.to_string(),
+                campaign_id: scope.campaign_id,
+                signs: signs.iter().filter(|s| !s.hidden).map(|s| SignView::new(pack, s)).collect()
+            })
+        },
+        ["history"] => {
+            let limit = query.get("limit")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                .clamp(1, MAX_HISTORY_LIMIT);
+
+            let pack = signs::scope_pack(dao, scope).await?;
+            let history = dao.get_sign_history(scope, limit).await?.iter()
+                .filter(|e| !e.sign.hidden)
+                .map(|e| HistoryView { sign: SignView::new(pack, &e.sign), discarded: e.discarded })
+                .collect::<Vec<_>>();
+
+            json(StatusCode::OK, &history)
+        },
+        ["users", user_id] => {
+            let Ok(user_id) = user_id.parse::<u64>() else {
+                return error(StatusCode::NOT_FOUND, "not found");
+            };
+
+            let Some(mut user_info) = dao.get_user_info(user_id, scope).await? else {
+                return error(StatusCode::NOT_FOUND, "user not found");
+            };
+
+            let settings = dao.get_guild_settings(scope).await?
+                .unwrap_or(GuildSettings::new(scope));
+            power::regress(&mut user_info, &settings, SystemTime::now());
+
+            json(StatusCode::OK, &UserView {
+                user_id: user_id.to_string(),
+                shaman_power: user_info.shaman_power,
+                rank: settings.ranks.get(user_info.shaman_power).map(|r| r.title.clone()),
+                luck_dice: user_info.luck_dice,
+                character: user_info.active_character_name
+            })
+        },
+        ["events"] => Ok(Response::builder()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .body(event_stream(scope))?),
+        _ => error(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+/**
+ * Endless stream of sign events of scope in server-sent events format
+ * Stream ends when client disconnects and body is dropped
+ */
+fn event_stream(scope: Scope) -> Body {
+    // Other events do not reset keepalive, so it is sent at least every KEEPALIVE
+    let keepalive = tokio::time::interval_at(Instant::now() + KEEPALIVE, KEEPALIVE);
+
+    let stream = stream::unfold((events::subscribe(), keepalive), move |(mut receiver, mut keepalive)| async move {
+        loop {
+            let chunk = tokio::select! {
+                res = receiver.recv() => match res {
+                    Ok(event) if event.scope == scope =>
+                        format!("event: {}\ndata: {}\n\n", event.event, serde_json::to_string(&event).ok()?),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Event stream of guild {} skipped {} events", scope.guild_id, skipped);
+                        continue;
+                    },
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = keepalive.tick() => ": keepalive\n\n".to_string(),
+            };
+
+            return Some((Ok::<_, Infallible>(Frame::data(Bytes::from(chunk))), (receiver, keepalive)));
+        }
+    });
+
+    StreamBody::new(stream).boxed_unsync()
+}
+
+/**
+ * Only hashes of tokens are stored
+ */
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn json(status: StatusCode, body: &impl Serialize) -> Result<Response<Body>> {
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Full::new(serde_json::to_vec(body)?.into()).boxed_unsync())?)
+}
+
+fn error(status: StatusCode, message: &str) -> Result<Response<Body>> {
+    json(status, &ErrorView { error: message })
+}
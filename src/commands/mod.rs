@@ -2,4 +2,19 @@ pub mod sign_roll;
 pub mod utils;
 pub mod sign_current;
 pub mod modify_sign;
-pub mod sign_my_power;
\ No newline at end of file
+pub mod sign_my_power;
+pub mod sign_pack;
+pub mod sign_luck;
+pub mod sign_settings;
+pub mod ritual;
+pub mod sign_session;
+pub mod sign_reveal;
+pub mod sign_campaign;
+pub mod sign_character;
+pub mod sign_roles;
+pub mod sign_stats;
+pub mod sign_guild_stats;
+pub mod sign_achievements;
+pub mod sign_webhook;
+pub mod sign_api_token;
+pub mod sign_setup;
\ No newline at end of file
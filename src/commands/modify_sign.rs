@@ -1,12 +1,20 @@
+use std::{cmp::Ordering, slice, time::SystemTime};
+
 use anyhow::Result;
 use indoc::formatdoc;
-use log::info;
+use log::{error, info};
 use rand::Rng;
-use serenity::all::{CacheHttp, ComponentInteraction, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage, EditMessage};
+use serenity::all::{CacheHttp, ComponentInteraction, Http, CreateInteractionResponse, CreateInteractionResponseMessage, EditMessage};
 
-use crate::{commands::utils, db::{SignState, UserInfo}, discord::Handler, signs::{self, render_sign}};
+use crate::{achievements::{self, Event}, commands::{ritual, utils}, db::{Dao, GuildSettings, ModifyRoll, SignState, UserInfo}, dice::{D20Roll, RollMode}, discord::Handler, events, power, roles, signs::{self, NextRoll}, webhooks};
 
-pub async fn run(handler: &Handler, ctx: impl CacheHttp, interaction: &mut ComponentInteraction) -> Result<CreateInteractionResponse> {
+/**
+ * Handles `change_sign:{normal|luck}:{sign_row_id}` button
+ * `luck` spends one luck die to roll with advantage
+ * Advantage and disadvantage also come from guild settings and active sign
+ * Buttons without sign row id (sent before several signs per day) modify first modifiable sign
+ */
+pub async fn run(handler: &Handler, ctx: impl CacheHttp, interaction: &mut ComponentInteraction, args: &[&str]) -> Result<CreateInteractionResponse> {
     let user_id = interaction.user.id.get();
     let guild_id = interaction.guild_id;
 
@@ -16,96 +24,236 @@ pub async fn run(handler: &Handler, ctx: impl CacheHttp, interaction: &mut Compo
 
     let guild_id = guild_id.unwrap().get();
     info!("Changing sign by user {} from guild {}", user_id, guild_id);
+    let use_luck = args.first() == Some(&"luck");
     let dao = handler.dao();
 
-    let user_info = dao.get_user_info(user_id, guild_id).await?;
-    let guild_info = dao.get_guild_info(guild_id).await?;
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+
+    let sign_row_id = match args.get(1) {
+        Some(id) => id.parse()?,
+        None => {
+            let guild_info = dao.get_guild_info(scope).await?;
+            match guild_info.as_ref().and_then(|g| g.first_modifiable()) {
+                Some(sign) => sign.row_id,
+                None => return Ok(utils::format_error("Сегодня нет знамения, на которое можно повлиять")),
+            }
+        }
+    };
+
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    if !utils::has_any_role(interaction.member.as_ref(), &settings, &settings.modify_role_ids) {
+        return Ok(utils::format_error("Влиять на знамения могут только шаманы, у тебя нет нужной роли"));
+    }
+
+    if settings.assist_max_helpers > 0 {
+        return ritual::start(handler, ctx, interaction, &settings, sign_row_id, use_luck).await;
+    }
+
+    let res = modify(dao.as_ref(), ctx.http(), sign_row_id, user_id, use_luck, &settings, &[]).await?;
+    if let Err(msg) = res {
+        return Ok(utils::format_error(msg));
+    }
+
+    let content = interaction.message.content.clone();
+    let signs = dao.get_guild_info(scope).await?
+        .map(|g| g.signs)
+        .unwrap_or_default();
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+
+    interaction.message.edit(ctx, EditMessage::new()
+        .content(content)
+        .components(utils::sign_buttons(pack, &signs, false))
+    ).await?;
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(res.unwrap())
+    ))
+}
+
+/**
+ * Rolls sign modification and saves its result
+ * Every helper adds `assist_bonus` from guild settings to the roll
+ * Rank roles of shaman are synced with new power
+ * Returns result message or Err with message for user if sign cannot be modified
+ */
+pub async fn modify(dao: &dyn Dao, http: &Http, sign_row_id: i64, user_id: u64, use_luck: bool, settings: &GuildSettings, helpers: &[u64]) -> Result<Result<String, String>> {
+    let scope = settings.scope();
+    let pack = signs::scope_pack(dao, scope).await?;
+    let user_info = dao.get_user_info(user_id, scope).await?;
+    let guild_info = dao.get_guild_info(scope).await?;
 
     if guild_info.is_none() {
-        return Ok(utils::format_error("Сегодня еще не было знамения. Ты можешь его создать!"));
+        return Ok(Err("Сегодня еще не было знамения. Ты можешь его создать!".to_string()));
     }
     let guild_info = guild_info.unwrap();
 
-    let mut user_info = match user_info {
-        Some(u) => u,
-        None => UserInfo { id: user_id, guild_id: guild_id, shaman_power: 10 }
-    };
+    let sign = guild_info.get_sign(sign_row_id);
+
+    if sign.is_none() {
+        return Ok(Err("Это знамение уже прошло".to_string()));
+    }
+    let sign = sign.unwrap();
+
+    if !sign.modifiable {
+        return Ok(Err("Это знамение нельзя изменить".to_string()));
+    }
+
+    if sign.hidden {
+        return Ok(Err("Это знамение еще скрыто".to_string()));
+    }
+
+    let mut user_info = user_info.unwrap_or(UserInfo::new(user_id, scope));
+    let now = SystemTime::now();
+    power::regress(&mut user_info, settings, now);
+    let power_before = user_info.shaman_power;
+
+    // Die is spent before roll and returned if sign cannot be changed
+    if use_luck && dao.add_luck_dice(user_id, scope, -1).await?.is_none() {
+        return Ok(Err("У тебя нет Костей Удачи".to_string()));
+    }
 
     let m = user_info.shaman_power / 2 - 5;
-    let roll = rand::thread_rng().gen_range(1..=20);
-    let value = roll + m;
-    let difficulty = signs::get_difficulty(guild_info.current_sign.id);
-    let mut shaman_power_decreased = false;
+    let bonus = helpers.len() as i32 * settings.assist_bonus;
+    let mode = RollMode::combine(&[
+        if use_luck {RollMode::Advantage} else {RollMode::Normal},
+        settings.modify_roll_mode,
+        pack.get_modify_roll_mode(&sign.id),
+    ]);
+    let d20 = D20Roll::roll(mode);
+    let roll = d20.result;
+    let value = roll + m + bonus;
+    let sign_id = sign.id.clone();
+    let difficulty = pack.get_difficulty(&sign_id);
     let mut success = false;
+    let critical = roll == 20 || roll == 1;
 
-    info!("Sign change: {} rolled {} and they modifyer is {}, difficulty is {}", user_id, roll, m, difficulty);
+    info!("Sign change: {} rolled {:?} ({}) and they modifyer is {}, helpers {:?} give {}, difficulty is {}", user_id, d20.dice, mode.as_str(), m, helpers, bonus, difficulty);
 
-    let state = if value >= difficulty {
+    // Natural 20 and natural 1 decide the outcome regardless of difficulty
+    let state = if roll == 20 || (roll != 1 && value >= difficulty) {
         if rand::thread_rng().gen_bool(0.5) {
-            shaman_power_decreased = true;
             user_info.shaman_power -= 1;
         }
         success = true;
-        SignState::Success { by_user_id: user_id }
+        if critical {
+            SignState::CriticalSuccess { by_user_id: user_id }
+        } else {
+            SignState::Success { by_user_id: user_id }
+        }
     } else {
         user_info.shaman_power += 1;
-        SignState::Failed { by_user_id: user_id }
+        if critical {
+            SignState::CriticalFailure { by_user_id: user_id }
+        } else {
+            SignState::Failed { by_user_id: user_id }
+        }
+    };
+
+    user_info.shaman_power = power::clamp(user_info.shaman_power, settings);
+    if user_info.shaman_power != power_before {
+        user_info.power_updated_at = now;
+    }
+
+    let modify_roll = ModifyRoll {
+        user_id,
+        sign_row_id,
+        d20: roll,
+        value,
+        success,
+        critical,
+        power_before,
+        power_after: user_info.shaman_power
     };
 
-    let res = dao.change_sign_state(guild_id, state).await?;
+    let res = dao.change_sign_state(scope, sign_row_id, state, &webhooks::sign_modified(scope, pack, modify_roll.clone())).await?;
     if res.is_err() {
+        if use_luck {
+            dao.add_luck_dice(user_id, scope, 1).await?;
+        }
+
         let res = res.err().unwrap();
         if res.is_none() {
-            return Ok(utils::format_error("Сегодня еще не было знамения. Ты можешь его создать!"));
+            return Ok(Err("Сегодня еще не было знамения. Ты можешь его создать!".to_string()));
         }
 
         let res = res.unwrap();
-        if res.current_sign.state != SignState::Created {
-            return Ok(utils::format_error("Кто-то уже повлиял на знамение сегодня"));
+        if res.state != SignState::Created {
+            return Ok(Err("Кто-то уже повлиял на знамение сегодня".to_string()));
         }
 
-        if res.current_sign.created_by_user_id == user_id {
-            return Ok(utils::format_error("Повлиять на знамение может только тот, кто его не создавал"));
+        if res.created_by_user_id == user_id {
+            return Ok(Err("Повлиять на знамение может только тот, кто его не создавал".to_string()));
         }
 
-        return Ok(utils::format_error("Ты не можешь повлиять на знамение сейчас"));
+        return Ok(Err("Ты не можешь повлиять на знамение сейчас".to_string()));
     }
 
-    dao.save_user_info(user_info.clone()).await?;
     let res = res.ok().unwrap();
-    let content = interaction.message.content.clone();
+    let outcome = pack.get_outcome(&sign_id, &res.state);
 
-    interaction.message.edit(ctx, EditMessage::new()
-        .content(content)
-        .button(CreateButton::new("change_sign")
-            .disabled(true)
-            .style(serenity::all::ButtonStyle::Primary)
-            .label("Повлиять на знамение")
-        )).await?;
+    dao.save_user_info(user_info.clone()).await?;
+    if outcome.luck_dice != 0 {
+        dao.add_luck_dice(user_id, scope, outcome.luck_dice).await?;
+    }
+    dao.add_modify_roll(scope, modify_roll.clone()).await?;
+    events::publish(scope, pack, webhooks::SIGN_MODIFIED, slice::from_ref(&res));
+    let unlocked = achievements::check(dao, user_id, scope, Event::Modified { sign_id: &sign_id, roll: &modify_roll }).await?;
+
+    if let Err(e) = roles::sync_user(http, settings, &user_info).await {
+        error!("Cannot sync rank roles of user {} in guild {}: {}", user_id, scope.guild_id, e);
+    }
+
+    if let Some(next_roll) = outcome.next_roll {
+        dao.set_next_roll(scope, Some(next_roll)).await?;
+    }
+
+    let mut rewards = String::new();
+    if !helpers.is_empty() {
+        let helpers = helpers.iter().map(|h| format!("<@{}>", h)).collect::<Vec<_>>().join(", ");
+        rewards.push_str(&format!("В ритуале помогали: {}\n", helpers));
+    }
+    if use_luck {
+        rewards.push_str("Потрачена Кость Удачи\n");
+    }
+    if outcome.luck_dice > 0 {
+        rewards.push_str(&format!("Получено Костей Удачи: {}\n", outcome.luck_dice));
+    }
+    match outcome.next_roll {
+        Some(NextRoll::ChooseTwo) => rewards.push_str("В следующий раз можно будет выбрать одно из двух знамений\n"),
+        Some(NextRoll::TwoUnmodifiable) => rewards.push_str("В следующий раз придут два знамения, которые нельзя изменить\n"),
+        None => {},
+    }
+    rewards.push_str(&achievements::render_unlocked(&unlocked));
 
     let result_message = formatdoc!(r#"
         __**Знамение изменено**__
-        __Бросок:__ d20 + ({}) = {}
-        *<@{}> попытался повлиять на судьбу, {}*
+        __Бросок:__ {} ({}) + ({}) + ({}) = {}
+        *{} попытался повлиять на судьбу, {}*
         Его шаманская сила {} и равна {}
-
+        {}
         {}"#,
-        m, roll + m,
-        interaction.user.id.get(),
-        if success {"и у него получилось"} else {"но сделал только хуже"},
-        if success && shaman_power_decreased {
-            "уменьшилась"
-        } else if !success {
-            "увеличилась"
-        } else {
-            "не изменилась"
+        d20.mode,
+        d20.dice_str(),
+        m, bonus, value,
+        utils::player_mention(&user_info),
+        match (success, critical) {
+            (true, true) => "и судьба сама пошла ему навстречу",
+            (true, false) => "и у него получилось",
+            (false, true) => "но навлек на всех беду",
+            (false, false) => "но сделал только хуже",
+        },
+        match user_info.shaman_power.cmp(&power_before) {
+            Ordering::Less => "уменьшилась",
+            Ordering::Greater => "увеличилась",
+            Ordering::Equal => "не изменилась",
         },
-        user_info.shaman_power,
-        render_sign(res.current_sign)
+        power::render_power(user_info.shaman_power, settings),
+        rewards,
+        pack.render_sign(&res)
     );
 
-    Ok(CreateInteractionResponse::Message(
-        CreateInteractionResponseMessage::new()
-            .content(result_message)
-    ))
+    Ok(Ok(result_message))
 }
\ No newline at end of file
@@ -0,0 +1,229 @@
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::{error, info};
+use serenity::all::{ButtonStyle, CacheHttp, ChannelId, ComponentInteraction, CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, EditMessage, Http, MessageId};
+
+use crate::{commands::{modify_sign, utils}, db::{Dao, GuildSettings, Ritual, SignState, UserInfo}, discord::Handler, signs};
+
+/**
+ * Starts group ritual instead of immediate sign modification
+ * Other players can join it with `ritual:help` until shaman resolves it with `ritual:resolve` or it expires
+ */
+pub async fn start(handler: &Handler, ctx: impl CacheHttp, interaction: &ComponentInteraction, settings: &GuildSettings, sign_row_id: i64, use_luck: bool) -> Result<CreateInteractionResponse> {
+    let user_id = interaction.user.id.get();
+    let guild_id = settings.guild_id;
+    let scope = settings.scope();
+    let dao = handler.dao();
+
+    let guild_info = dao.get_guild_info(scope).await?;
+
+    if guild_info.is_none() {
+        return Ok(utils::format_error("Сегодня еще не было знамения. Ты можешь его создать!"));
+    }
+    let guild_info = guild_info.unwrap();
+
+    match guild_info.get_sign(sign_row_id) {
+        None => return Ok(utils::format_error("Это знамение уже прошло")),
+        Some(sign) if !sign.modifiable => return Ok(utils::format_error("Это знамение нельзя изменить")),
+        Some(sign) if sign.hidden => return Ok(utils::format_error("Это знамение еще скрыто")),
+        Some(sign) if sign.state != SignState::Created => return Ok(utils::format_error("Кто-то уже повлиял на знамение сегодня")),
+        Some(sign) if sign.created_by_user_id == user_id => return Ok(utils::format_error("Повлиять на знамение может только тот, кто его не создавал")),
+        Some(_) => {},
+    }
+
+    if use_luck {
+        let user_info = dao.get_user_info(user_id, scope).await?
+            .unwrap_or(UserInfo::new(user_id, scope));
+
+        if user_info.luck_dice <= 0 {
+            return Ok(utils::format_error("У тебя нет Костей Удачи"));
+        }
+    }
+
+    let ritual = dao.create_ritual(scope, sign_row_id, user_id, use_luck, interaction.channel_id.get(), settings.assist_timeout_secs).await?;
+
+    if ritual.is_none() {
+        return Ok(utils::format_error("Ритуал уже начат, ты можешь в нем помочь"));
+    }
+    let ritual = ritual.unwrap();
+
+    info!("User {} started ritual in guild {}", user_id, guild_id);
+
+    // Ritual message is posted separately, so its buttons can be disabled when ritual expires
+    let message = interaction.channel_id.send_message(ctx.http(), CreateMessage::new()
+        .content(render_ritual(&ritual, settings))
+        .components(ritual_buttons(false))
+    ).await?;
+    dao.set_ritual_message(scope, message.id.get()).await?;
+
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+
+    Ok(CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .components(utils::sign_buttons(pack, &guild_info.signs, true))
+    ))
+}
+
+/**
+ * Handles ritual buttons: `ritual:help` and `ritual:resolve`
+ */
+pub async fn run_component(handler: &Handler, ctx: impl CacheHttp, interaction: &mut ComponentInteraction, args: &[&str]) -> Result<CreateInteractionResponse> {
+    let user_id = interaction.user.id.get();
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    match args {
+        ["help"] => {
+            let ritual = dao.get_ritual(scope).await?;
+
+            if ritual.is_none() {
+                return Ok(utils::format_error("Ритуал уже завершен"));
+            }
+            let ritual = ritual.unwrap();
+
+            if ritual.shaman_id == user_id {
+                return Ok(utils::format_error("Шаман не может помогать сам себе"));
+            }
+
+            if !utils::has_any_role(interaction.member.as_ref(), &settings, &settings.modify_role_ids) {
+                return Ok(utils::format_error("Помогать в ритуале могут только шаманы, у тебя нет нужной роли"));
+            }
+
+            let ritual = dao.add_ritual_helper(scope, user_id, settings.assist_max_helpers).await?;
+
+            if ritual.is_none() {
+                return Ok(utils::format_error("Ты уже помогаешь или ритуалу больше не нужна помощь"));
+            }
+            let ritual = ritual.unwrap();
+
+            info!("User {} helps ritual in guild {}", user_id, guild_id);
+
+            Ok(CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(render_ritual(&ritual, &settings))
+                    .components(ritual_buttons(false))
+            ))
+        },
+        ["resolve"] => {
+            let ritual = dao.get_ritual(scope).await?;
+
+            if ritual.is_none() {
+                return Ok(utils::format_error("Ритуал уже завершен"));
+            }
+
+            if ritual.unwrap().shaman_id != user_id {
+                return Ok(utils::format_error("Провести ритуал может только тот, кто его начал"));
+            }
+
+            let ritual = dao.take_ritual(scope).await?;
+
+            if ritual.is_none() {
+                return Ok(utils::format_error("Ритуал уже завершен"));
+            }
+            let ritual = ritual.unwrap();
+
+            let res = modify_sign::modify(dao.as_ref(), ctx.http(), ritual.sign_row_id, ritual.shaman_id, ritual.use_luck, &settings, &ritual.helpers).await?;
+
+            let content = interaction.message.content.clone();
+
+            interaction.message.edit(ctx, EditMessage::new()
+                .content(content)
+                .components(ritual_buttons(true))
+            ).await?;
+
+            match res {
+                Ok(content) => Ok(CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(content)
+                )),
+                Err(msg) => Ok(utils::format_error(msg)),
+            }
+        },
+        _ => Err(anyhow!(format!("Unknown ritual action {:?}", args))),
+    }
+}
+
+/**
+ * Resolves rituals which shaman did not finish in time and posts results to ritual channels
+ * Rituals are stored in db, so ones expired during restart are resolved too
+ */
+pub async fn resolve_expired(dao: &dyn Dao, http: &Http) -> Result<()> {
+    let rituals = dao.take_expired_rituals().await?;
+
+    for ritual in rituals {
+        info!("Ritual in guild {} expired, resolving", ritual.guild_id);
+
+        let settings = dao.get_guild_settings(ritual.scope()).await?
+            .unwrap_or(GuildSettings::new(ritual.scope()));
+
+        let content = match modify_sign::modify(dao, http, ritual.sign_row_id, ritual.shaman_id, ritual.use_luck, &settings, &ritual.helpers).await? {
+            Ok(content) => content,
+            Err(msg) => format!("**Ритуал не удался:**\n{}", msg),
+        };
+
+        let channel = ChannelId::new(ritual.channel_id);
+
+        if let Some(message_id) = ritual.message_id {
+            let res = channel.edit_message(http, MessageId::new(message_id), EditMessage::new()
+                .components(ritual_buttons(true))
+            ).await;
+
+            if let Err(e) = res {
+                error!("Cannot disable buttons of ritual message {}: {}", message_id, e);
+            }
+        }
+
+        let res = channel.send_message(http, CreateMessage::new().content(content)).await;
+
+        if let Err(e) = res {
+            error!("Cannot send ritual result to channel {}: {}", ritual.channel_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+fn render_ritual(ritual: &Ritual, settings: &GuildSettings) -> String {
+    let helpers = if ritual.helpers.is_empty() {
+        "пока никого".to_string()
+    } else {
+        ritual.helpers.iter().map(|h| format!("<@{}>", h)).collect::<Vec<_>>().join(", ")
+    };
+
+    formatdoc!(r#"
+        __**Ритуал**__
+        *<@{}> пытается повлиять на знамение{}*
+        Каждый помощник дает +{} к броску, ритуал завершится сам через {} сек.
+        **Помогают ({}/{}):** {}
+        "#,
+        ritual.shaman_id,
+        if ritual.use_luck {" с Костью Удачи"} else {""},
+        settings.assist_bonus,
+        settings.assist_timeout_secs,
+        ritual.helpers.len(),
+        settings.assist_max_helpers,
+        helpers
+    )
+}
+
+fn ritual_buttons(disabled: bool) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("ritual:help")
+            .disabled(disabled)
+            .style(ButtonStyle::Secondary)
+            .label("Помочь"),
+        CreateButton::new("ritual:resolve")
+            .disabled(disabled)
+            .style(ButtonStyle::Primary)
+            .label("Провести ритуал"),
+    ])]
+}
@@ -0,0 +1,70 @@
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Result};
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, ResolvedValue};
+
+use crate::{achievements, commands::utils, db::UnlockedAchievement, discord::Handler};
+
+/**
+ * Lists achievements of player, caller by default
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let mut target = interaction.user.id.get();
+
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("user", ResolvedValue::User(user, _)) => target = user.id.get(),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    if achievements::list_achievements().is_empty() {
+        return Ok(utils::format_error("Достижения не настроены"));
+    }
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let unlocked = dao.get_achievements(target, scope).await?;
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(render_achievements(target, &unlocked))
+            .ephemeral(true)
+    ))
+}
+
+fn render_achievements(user_id: u64, unlocked: &[UnlockedAchievement]) -> String {
+    let total = achievements::list_achievements().len();
+
+    // Achievements removed from data file are not shown
+    let list = unlocked.iter()
+        .filter_map(|u| achievements::get_achievement(&u.achievement_id).map(|a| (a, u.unlocked_at)))
+        .map(|(a, unlocked_at)| format!(
+            "🏆 **{}**: {} (<t:{}:d>)",
+            a.name,
+            a.description,
+            unlocked_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+        ))
+        .collect::<Vec<_>>();
+
+    if list.is_empty() {
+        return format!("У <@{}> пока нет достижений, всего их {}", user_id, total);
+    }
+
+    format!("__**Достижения <@{}>**__ ({} из {})\n{}", user_id, list.len(), total, list.join("\n"))
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_achievements")
+        .description("Show achievements of player")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "Player, you by default")
+        )
+}
@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::info;
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions, ResolvedOption, ResolvedValue};
+
+use crate::{api, commands::utils, db::ApiToken, discord::Handler};
+
+/**
+ * GM command to manage tokens of read-only REST API
+ * Token is shown once, only its hash is stored
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let options = interaction.data.options();
+    let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(options), .. }) = options.first() else {
+        return Err(anyhow!("Api token subcommand is not set"));
+    };
+
+    let mut name = None;
+
+    for option in options {
+        match (option.name, &option.value) {
+            ("name", ResolvedValue::String(value)) => name = Some(value.trim().to_string()),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let dao = handler.dao();
+
+    let content = match *subcommand {
+        "create" => {
+            let name = name.ok_or(anyhow!("Name option is not set"))?;
+            let token = utils::random_token();
+
+            let created = dao.create_api_token(guild_id, name.clone(), api::hash_token(&token), interaction.user.id.get()).await?;
+
+            if created.is_none() {
+                return Ok(utils::format_error(format!("Токен «{}» уже есть", name)));
+            }
+
+            info!("User {} created api token {} in guild {}", interaction.user.id, name, guild_id);
+
+            formatdoc!(r#"
+                Токен «{}» создан: `{}`
+                *Он показан только сейчас. Передавайте его в заголовке `Authorization: Bearer {{токен}}`*
+                *Например: `GET /api/guilds/{}/sign`*"#,
+                name, token, guild_id
+            )
+        },
+        "revoke" => {
+            let name = name.ok_or(anyhow!("Name option is not set"))?;
+
+            if !dao.delete_api_token(guild_id, name.clone()).await? {
+                return Ok(utils::format_error(format!("Токена «{}» нет", name)));
+            }
+
+            info!("User {} revoked api token {} in guild {}", interaction.user.id, name, guild_id);
+
+            format!("Токен «{}» отозван", name)
+        },
+        "list" => render_tokens(&dao.get_api_tokens(guild_id).await?),
+        cmd => return Err(anyhow!(format!("Unknown api token subcommand {}", cmd))),
+    };
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true)
+    ))
+}
+
+fn render_tokens(tokens: &[ApiToken]) -> String {
+    if tokens.is_empty() {
+        return "Токенов нет".to_string();
+    }
+
+    let list = tokens.iter()
+        .map(|t| format!("**{}**: создан <@{}>", t.name, t.created_by_user_id))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("__**Токены API**__\n{}", list)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_api_token")
+        .description("Manage tokens of read-only API (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "create", "Create token, it is shown once")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "name", "Name of token, e.g. tool using it")
+                        .required(true)
+                        .max_length(100)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "revoke", "Revoke token")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "name", "Name of token")
+                        .required(true)
+                        .max_length(100)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List tokens")
+        )
+}
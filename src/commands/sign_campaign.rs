@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::info;
+use serenity::all::{CacheHttp, ChannelType, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions, ResolvedOption, ResolvedValue};
+
+use crate::{commands::utils, db::Campaign, discord::Handler, signs};
+
+/**
+ * GM command to manage campaigns of guild
+ * Channel bound to campaign has its own signs, shaman power and settings
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let options = interaction.data.options();
+    let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(options), .. }) = options.first() else {
+        return Err(anyhow!("Campaign subcommand is not set"));
+    };
+
+    let mut name = None;
+    let mut pack = None;
+    let mut channel_id = interaction.channel_id.get();
+
+    for option in options {
+        match (option.name, &option.value) {
+            ("name", ResolvedValue::String(value)) => name = Some(value.trim().to_string()),
+            ("pack", ResolvedValue::String(value)) => pack = Some(value.to_string()),
+            ("channel", ResolvedValue::Channel(channel)) => channel_id = channel.id.get(),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let dao = handler.dao();
+
+    let content = match *subcommand {
+        "create" => {
+            let name = name.ok_or(anyhow!("Name option is not set"))?;
+
+            if let Some(pack) = pack.as_deref().filter(|p| !signs::has_pack(p)) {
+                return Ok(utils::format_error(format!("Колоды «{}» нет", pack)));
+            }
+
+            let campaign = dao.create_campaign(guild_id, name.clone(), pack).await?;
+
+            if campaign.is_none() {
+                return Ok(utils::format_error(format!("Кампания «{}» уже есть", name)));
+            }
+
+            info!("User {} created campaign {} in guild {}", interaction.user.id, name, guild_id);
+
+            format!("Кампания «{}» создана, привяжи к ней каналы командой `/sign_campaign bind`", name)
+        },
+        "bind" => {
+            let name = name.ok_or(anyhow!("Name option is not set"))?;
+            let campaign = dao.bind_channel(guild_id, name.clone(), channel_id).await?;
+
+            if campaign.is_none() {
+                return Ok(utils::format_error(format!("Кампании «{}» нет", name)));
+            }
+
+            info!("User {} bound channel {} to campaign {} in guild {}", interaction.user.id, channel_id, name, guild_id);
+
+            format!("Канал <#{}> теперь относится к кампании «{}»", channel_id, name)
+        },
+        "unbind" => {
+            if !dao.unbind_channel(guild_id, channel_id).await? {
+                return Ok(utils::format_error("Канал не привязан к кампании"));
+            }
+
+            info!("User {} unbound channel {} in guild {}", interaction.user.id, channel_id, guild_id);
+
+            format!("Канал <#{}> больше не относится к кампании", channel_id)
+        },
+        "list" => render_campaigns(&dao.get_campaigns(guild_id).await?),
+        cmd => return Err(anyhow!(format!("Unknown campaign subcommand {}", cmd))),
+    };
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true)
+    ))
+}
+
+fn render_campaigns(campaigns: &[Campaign]) -> String {
+    if campaigns.is_empty() {
+        return "Кампаний нет, знамения общие для всего сервера".to_string();
+    }
+
+    let list = campaigns.iter()
+        .map(|c| {
+            let channels = if c.channels.is_empty() {
+                "нет каналов".to_string()
+            } else {
+                c.channels.iter().map(|ch| format!("<#{}>", ch)).collect::<Vec<_>>().join(", ")
+            };
+            format!("**{}** ({}): {}", c.name, c.pack.as_deref().unwrap_or("колода сервера"), channels)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    formatdoc!(r#"
+        __**Кампании**__
+        {}
+        *В остальных каналах знамения общие для всего сервера*"#,
+        list
+    )
+}
+
+pub fn register() -> CreateCommand {
+    let pack = signs::pack_names().into_iter()
+        .fold(
+            CreateCommandOption::new(CommandOptionType::String, "pack", "Sign pack of campaign, pack of guild by default"),
+            |option, pack| option.add_string_choice(pack, pack)
+        );
+
+    let channel = || CreateCommandOption::new(CommandOptionType::Channel, "channel", "Channel, current one by default")
+        .channel_types(vec![ChannelType::Text]);
+
+    CreateCommand::new("sign_campaign")
+        .description("Manage campaigns with separate signs (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "create", "Create campaign")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "name", "Campaign name")
+                        .required(true)
+                        .max_length(100)
+                )
+                .add_sub_option(pack)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "bind", "Bind channel to campaign")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "name", "Campaign name")
+                        .required(true)
+                )
+                .add_sub_option(channel())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "unbind", "Return channel to guild-wide signs")
+                .add_sub_option(channel())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List campaigns and their channels")
+        )
+}
@@ -0,0 +1,116 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, ResolvedOption, ResolvedValue};
+
+use crate::{commands::utils, db::{Character, UserInfo}, discord::Handler};
+
+/**
+ * Manages characters of player, active character modifies signs with its own shaman power
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let user_id = interaction.user.id.get();
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let options = interaction.data.options();
+    let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(options), .. }) = options.first() else {
+        return Err(anyhow!("Character subcommand is not set"));
+    };
+
+    let mut name = None;
+
+    for option in options {
+        match (option.name, &option.value) {
+            ("name", ResolvedValue::String(value)) => name = Some(value.trim().to_string()),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+
+    let content = match *subcommand {
+        "create" => {
+            let name = name.ok_or(anyhow!("Name option is not set"))?;
+            let character = dao.create_character(user_id, scope, name.clone()).await?;
+
+            if character.is_none() {
+                return Ok(utils::format_error(format!("У тебя уже есть персонаж «{}»", name)));
+            }
+
+            info!("User {} created character {} in guild {}", user_id, name, guild_id);
+
+            format!("Персонаж «{}» создан, выбери его командой `/sign_character switch`", name)
+        },
+        "switch" => {
+            if !dao.set_active_character(user_id, scope, name.clone()).await? {
+                return Ok(utils::format_error(format!("У тебя нет персонажа «{}»", name.unwrap_or_default())));
+            }
+
+            info!("User {} switched to character {:?} in guild {}", user_id, name, guild_id);
+
+            match name {
+                Some(name) => format!("Теперь знамения меняет «{}»", name),
+                None => "Теперь знамения меняешь ты сам".to_string(),
+            }
+        },
+        "list" => {
+            let characters = dao.get_characters(user_id, scope).await?;
+            let user_info = dao.get_user_info(user_id, scope).await?
+                .unwrap_or(UserInfo::new(user_id, scope));
+
+            render_characters(&characters, user_info.active_character_id)
+        },
+        cmd => return Err(anyhow!(format!("Unknown character subcommand {}", cmd))),
+    };
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true)
+    ))
+}
+
+fn render_characters(characters: &[Character], active_id: Option<i64>) -> String {
+    if characters.is_empty() {
+        return "У тебя нет персонажей, создай их командой `/sign_character create`".to_string();
+    }
+
+    let list = characters.iter()
+        .map(|c| format!(
+            "{}**{}**: сила шамана {}",
+            if Some(c.id) == active_id {"▶ "} else {""},
+            c.name,
+            c.shaman_power
+        ))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("__**Персонажи**__\n{}", list)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_character")
+        .description("Manage your characters with separate shaman power")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "create", "Create character")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "name", "Character name")
+                        .required(true)
+                        .max_length(100)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "switch", "Switch active character, without name switch to yourself")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "name", "Character name")
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List your characters")
+        )
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
-use serenity::all::{CacheHttp, CommandInteraction, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::all::{CacheHttp, CommandInteraction, CreateActionRow, CreateButton, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage};
 
-use crate::{commands::utils, discord::Handler, signs};
+use crate::{commands::utils, db::GuildSettings, discord::Handler, pages, signs};
 
 pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
     let guild_id = interaction.guild_id;
@@ -11,7 +11,9 @@ pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandI
     }
     let guild_id = guild_id.unwrap();
 
-    let guild_info = handler.dao().get_guild_info(guild_id.get()).await?;
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id.get(), interaction.channel_id).await?;
+    let guild_info = dao.get_guild_info(scope).await?;
 
     if guild_info.is_none() {
         return Ok(CreateInteractionResponse::Message(
@@ -22,9 +24,21 @@ pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandI
     }
 
     let guild_info = guild_info.unwrap();
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+    let (content, embeds) = utils::render_signs(pack, &guild_info.signs, utils::is_gm(interaction.member.as_deref(), &settings));
+
+    // Page is shared with players who have no Discord open
+    let components = pages::link(scope)
+        .map(|url| vec![CreateActionRow::Buttons(vec![CreateButton::new_link(url).label("Открыть в браузере")])])
+        .unwrap_or_default();
+
     Ok(CreateInteractionResponse::Message(
         CreateInteractionResponseMessage::new()
-            .content(signs::render_sign(guild_info.current_sign))
+            .content(content)
+            .embeds(embeds)
+            .components(components)
             .ephemeral(true)
     ))
 }
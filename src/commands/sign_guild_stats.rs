@@ -0,0 +1,67 @@
+use anyhow::Result;
+use serenity::all::{CacheHttp, CommandInteraction, CreateCommand, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions};
+
+use crate::{commands::utils, db::{SignStats, Streaks}, discord::Handler, signs::{self, Pack}};
+
+/**
+ * GM command to compare how often signs came up with their probability on 4d4
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let stats = dao.get_sign_stats(scope).await?;
+
+    if stats.is_empty() {
+        return Ok(utils::format_error("Знамений еще не было"));
+    }
+
+    let streaks = dao.get_streaks(scope).await?;
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .embed(render_stats(pack, &stats, &streaks))
+            .ephemeral(true)
+    ))
+}
+
+fn render_stats(pack: &Pack, stats: &[SignStats], streaks: &Streaks) -> CreateEmbed {
+    let total: i64 = stats.iter().map(|s| s.rolled).sum();
+    let never_rolled = pack.list_signs().iter()
+        .filter(|sign| stats.iter().all(|s| s.sign_id != sign.id))
+        .count();
+
+    let lines = stats.iter()
+        .map(|s| format!(
+            "`{}` {}: {} ({:.1}%, ожидалось {:.1}%), удачно {}, неудачно {}",
+            s.sign_id,
+            pack.get_name(&s.sign_id),
+            s.rolled,
+            s.rolled as f64 * 100.0 / total as f64,
+            signs::sign_probability(&s.sign_id) * 100.0,
+            s.successes,
+            s.failures
+        ))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    CreateEmbed::new()
+        .title(format!("Знамения: {}", total))
+        .description(lines)
+        .field("Удачи подряд", streaks.success.to_string(), true)
+        .field("Неудачи подряд", streaks.failure.to_string(), true)
+        .footer(CreateEmbedFooter::new(format!("Ни разу не выпадало знамений: {}", never_rolled)))
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_guild_stats")
+        .description("Show how often signs came up compared to their probability (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+}
@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions, ResolvedValue};
+
+use crate::{commands::utils, db::UserInfo, discord::Handler};
+
+/// Bound of luck dice given or taken at once
+const MAX_AMOUNT: i64 = 100;
+
+/**
+ * GM command to give (or take with negative amount) luck dice
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let mut target = None;
+    let mut amount = None;
+
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("user", ResolvedValue::User(user, _)) => target = Some(user.id.get()),
+            ("amount", ResolvedValue::Integer(value)) => amount = Some(value),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let target = target.ok_or(anyhow!("User option is not set"))?;
+    let amount = amount.ok_or(anyhow!("Amount option is not set"))?;
+
+    if !(-MAX_AMOUNT..=MAX_AMOUNT).contains(&amount) {
+        return Ok(utils::format_error(format!("За раз можно дать или забрать не больше {} Костей Удачи", MAX_AMOUNT)));
+    }
+    let amount: i32 = amount.try_into()?;
+
+    info!("User {} gives {} luck dice to user {} in guild {}", interaction.user.id, amount, target, guild_id);
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let luck_dice = dao.add_luck_dice(target, scope, amount).await?;
+
+    let Some(luck_dice) = luck_dice else {
+        let user_info = dao.get_user_info(target, scope).await?
+            .unwrap_or(UserInfo::new(target, scope));
+
+        return Ok(utils::format_error(format!("У игрока только {} Костей Удачи", user_info.luck_dice)));
+    };
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(format!("Кости Удачи <@{}>: {} ({:+})", target, luck_dice, amount))
+    ))
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_luck")
+        .description("Give or take luck dice (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "Player")
+                .required(true)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "amount", "Amount of luck dice, negative to take")
+                .required(true)
+        )
+}
@@ -1,8 +1,10 @@
+use std::time::SystemTime;
+
 use anyhow::Result;
 use log::info;
 use serenity::all::{CacheHttp, CommandInteraction, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage};
 
-use crate::{commands::utils, discord::Handler};
+use crate::{commands::utils, db::{GuildSettings, UserInfo}, discord::Handler, power};
 
 pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
     let user_id = interaction.user.id;
@@ -17,13 +19,24 @@ pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandI
     let guild_id = guild_id.unwrap();
     info!("Rolling sign for user {} from guild {}", user_id, guild_id);
 
-    let user = handler.dao().get_user_info(user_id.get(), guild_id.get()).await?;
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id.get(), interaction.channel_id).await?;
+    let user = dao.get_user_info(user_id.get(), scope).await?;
+
+    let mut user = user.unwrap_or(UserInfo::new(user_id.get(), scope));
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
 
-    let power = user.map_or(10, |u| u.shaman_power);
+    // Regressed power is shown, but saved only when it changes with next modification
+    power::regress(&mut user, &settings, SystemTime::now());
+    let shaman_power = power::render_power(user.shaman_power, &settings);
 
     let msg = CreateInteractionResponse::Message(
         CreateInteractionResponseMessage::new()
-            .content(format!("Твоя сила шамана: {}", power))
+            .content(match &user.active_character_name {
+                Some(name) => format!("Персонаж: {}\nСила шамана: {}\nКости Удачи: {}", name, shaman_power, user.luck_dice),
+                None => format!("Твоя сила шамана: {}\nКости Удачи: {}", shaman_power, user.luck_dice),
+            })
             .ephemeral(true)
     );
 
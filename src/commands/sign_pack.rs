@@ -0,0 +1,144 @@
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::info;
+use serenity::all::{CacheHttp, ChannelId, CommandInteraction, ComponentInteraction, CreateActionRow, CreateButton, CreateCommand, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, GuildId};
+
+use crate::{commands::utils, discord::Handler, signs::{self, Pack}};
+
+const PAGE_SIZE: usize = 5;
+const FIELD_LIMIT: usize = 1024;
+/// Discord allows no more options in select menu
+const MAX_OPTIONS: usize = 25;
+
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    info!("Showing sign pack to user {}", interaction.user.id);
+
+    let pack = channel_pack(handler, interaction.guild_id, interaction.channel_id).await?;
+    let Some((embed, components)) = render_page(pack, 0) else {
+        return Ok(utils::format_error("В колоде нет знамений"));
+    };
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .embed(embed)
+            .components(components)
+            .ephemeral(true)
+    ))
+}
+
+/**
+ * Handles pack browser components
+ * Page state is kept in custom_id: `sign_pack:prev:{page}`, `sign_pack:next:{page}` and `sign_pack:select`
+ */
+pub async fn run_component(handler: &Handler, _ctx: impl CacheHttp, interaction: &ComponentInteraction, args: &[&str], values: &[String]) -> Result<CreateInteractionResponse> {
+    let page = match args {
+        ["prev", page] => page.parse::<usize>()?.saturating_sub(1),
+        ["next", page] => page.parse::<usize>()? + 1,
+        ["select"] => values.first().ok_or(anyhow!("Page is not selected"))?.parse()?,
+        _ => return Err(anyhow!(format!("Unknown pack browser action {:?}", args))),
+    };
+
+    info!("Showing sign pack page {} to user {}", page, interaction.user.id);
+
+    let pack = channel_pack(handler, interaction.guild_id, interaction.channel_id).await?;
+    let Some((embed, components)) = render_page(pack, page) else {
+        return Ok(utils::format_error("В колоде нет знамений"));
+    };
+
+    Ok(CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .embed(embed)
+            .components(components)
+    ))
+}
+
+/**
+ * Pack of campaign or guild where command is used, default pack in direct messages
+ */
+async fn channel_pack(handler: &Handler, guild_id: Option<GuildId>, channel_id: ChannelId) -> Result<&'static Pack> {
+    let Some(guild_id) = guild_id else {
+        return Ok(signs::get_pack(None));
+    };
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id.get(), channel_id).await?;
+
+    signs::scope_pack(dao.as_ref(), scope).await
+}
+
+/**
+ * Page of pack browser, None if pack is empty
+ * Select menu lists pages around current one when there are too many of them
+ */
+fn render_page(pack: &Pack, page: usize) -> Option<(CreateEmbed, Vec<CreateActionRow>)> {
+    let signs = pack.list_signs();
+    if signs.is_empty() {
+        return None;
+    }
+
+    let pages = signs.len().div_ceil(PAGE_SIZE);
+    let page = page.min(pages - 1);
+
+    let fields = signs.iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|s| {
+            let value = formatdoc!(r#"
+                **Сложность:** {}
+                **Эффект:** {}
+                **Успех:** {}
+                **Провал:** {}"#,
+                s.difficulty, s.effect, s.success_effect, s.failure_effect
+            );
+            (format!("{} — {}", s.id, s.name), truncate(value, FIELD_LIMIT), false)
+        });
+
+    let embed = CreateEmbed::new()
+        .title(format!("Знамения: {}", pack.name))
+        .fields(fields)
+        .footer(CreateEmbedFooter::new(format!("Страница {} из {}", page + 1, pages)));
+
+    let first_option = page.saturating_sub(MAX_OPTIONS / 2).min(pages.saturating_sub(MAX_OPTIONS));
+    let options = (first_option..pages)
+        .take(MAX_OPTIONS)
+        .map(|p| {
+            let first = &signs[p * PAGE_SIZE].id;
+            let last = &signs[((p + 1) * PAGE_SIZE).min(signs.len()) - 1].id;
+            CreateSelectMenuOption::new(format!("Страница {}: {}–{}", p + 1, first, last), p.to_string())
+                .default_selection(p == page)
+        })
+        .collect();
+
+    let components = vec![
+        CreateActionRow::Buttons(vec![
+            CreateButton::new(format!("sign_pack:prev:{}", page))
+                .style(serenity::all::ButtonStyle::Secondary)
+                .label("Назад")
+                .disabled(page == 0),
+            CreateButton::new(format!("sign_pack:next:{}", page))
+                .style(serenity::all::ButtonStyle::Secondary)
+                .label("Вперед")
+                .disabled(page + 1 >= pages),
+        ]),
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new("sign_pack:select", CreateSelectMenuKind::String { options })
+                .placeholder("Перейти к странице")
+        ),
+    ];
+
+    Some((embed, components))
+}
+
+fn truncate(value: String, limit: usize) -> String {
+    if value.chars().count() <= limit {
+        return value;
+    }
+
+    let mut res: String = value.chars().take(limit - 1).collect();
+    res.push('…');
+    res
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_pack").description("Browse all signs of the active sign pack")
+}
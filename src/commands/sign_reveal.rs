@@ -0,0 +1,104 @@
+use anyhow::Result;
+use log::info;
+use serenity::all::{ButtonStyle, CacheHttp, ChannelId, CommandInteraction, ComponentInteraction, CreateActionRow, CreateButton, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage};
+
+use crate::{commands::{sign_roll, utils}, db::GuildSettings, discord::Handler, events, signs, webhooks};
+
+/**
+ * GM command to publish hidden signs of current session
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    if !utils::is_gm(interaction.member.as_deref(), &settings) {
+        return Ok(utils::format_error("Открыть скрытое знамение может только ГМ"));
+    }
+
+    reveal(handler, guild_id, interaction.channel_id, interaction.user.id.get()).await
+}
+
+/**
+ * Handles `sign_reveal` button attached to secret roll
+ */
+pub async fn run_component(handler: &Handler, _ctx: impl CacheHttp, interaction: &ComponentInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    if !utils::is_gm(interaction.member.as_ref(), &settings) {
+        return Ok(utils::format_error("Открыть скрытое знамение может только ГМ"));
+    }
+
+    reveal(handler, guild_id, interaction.channel_id, interaction.user.id.get()).await
+}
+
+async fn reveal(handler: &Handler, guild_id: u64, channel_id: ChannelId, user_id: u64) -> Result<CreateInteractionResponse> {
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, channel_id).await?;
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+    let signs = dao.reveal_signs(scope, &webhooks::signs_revealed(scope, pack)).await?;
+
+    if signs.is_empty() {
+        return Ok(utils::format_error("Скрытых знамений нет"));
+    }
+
+    info!("User {} revealed {} signs in guild {}", user_id, signs.len(), guild_id);
+    events::publish(scope, pack, webhooks::SIGN_REVEALED, &signs);
+
+    // Achievements for rolling hidden signs are given only now
+    let mut creators = signs.iter().map(|s| s.created_by_user_id).collect::<Vec<_>>();
+    creators.sort();
+    creators.dedup();
+
+    let mut announcement = String::new();
+    for creator in creators {
+        let created = signs.iter().filter(|s| s.created_by_user_id == creator);
+        let unlocked = sign_roll::roll_achievements(dao.as_ref(), creator, scope, created).await?;
+
+        if !unlocked.is_empty() {
+            announcement.push_str(&format!("<@{}>\n{}", creator, unlocked));
+        }
+    }
+
+    let (mut content, embeds) = utils::render_signs(pack, &signs, false);
+    content.push_str(&announcement);
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .embeds(embeds)
+            .components(utils::sign_buttons(pack, &signs, false))
+    ))
+}
+
+pub fn reveal_button() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new("sign_reveal")
+            .style(ButtonStyle::Success)
+            .label("Открыть знамение"),
+    ])
+}
+
+pub fn register() -> CreateCommand {
+    // Members with GM role from settings should see command too, so access is checked on run
+    CreateCommand::new("sign_reveal")
+        .description("Reveal hidden signs of current session (GM only)")
+}
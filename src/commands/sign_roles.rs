@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::info;
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions, ResolvedOption, ResolvedValue};
+
+use crate::{commands::utils, db::{GuildSettings, Scope}, discord::Handler, power::RankRoles, roles};
+
+/**
+ * GM command to map shaman power thresholds to Discord roles of ranks
+ * Roles are synced after every power change and for all members with `sync`
+ * Roles are shared by the whole guild, so only power outside of campaigns gives them
+ */
+pub async fn run(handler: &Handler, ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let options = interaction.data.options();
+    let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(options), .. }) = options.first() else {
+        return Err(anyhow!("Roles subcommand is not set"));
+    };
+
+    let mut power = None;
+    let mut role_id = None;
+
+    for option in options {
+        match (option.name, &option.value) {
+            ("power", ResolvedValue::Integer(value)) => power = Some(i32::try_from(*value)?),
+            ("role", ResolvedValue::Role(role)) => role_id = Some(role.id.get()),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let dao = handler.dao();
+    let scope = Scope::guild(guild_id);
+    let mut settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    let content = match *subcommand {
+        "set" => {
+            let power = power.ok_or(anyhow!("Power option is not set"))?;
+            let role_id = role_id.ok_or(anyhow!("Role option is not set"))?;
+
+            settings.rank_roles.set(power, role_id);
+            dao.save_guild_settings(settings.clone()).await?;
+
+            info!("User {} mapped power {} to role {} in guild {}", interaction.user.id, power, role_id, guild_id);
+
+            format!("Роль <@&{}> выдается с силой шамана от {}\nЧтобы выдать ее всем, используй `/sign_roles sync`", role_id, power)
+        },
+        "remove" => {
+            let role_id = role_id.ok_or(anyhow!("Role option is not set"))?;
+
+            if !settings.rank_roles.remove(role_id) {
+                return Ok(utils::format_error("Эта роль не выдается за силу шамана"));
+            }
+            dao.save_guild_settings(settings.clone()).await?;
+
+            info!("User {} removed rank role {} in guild {}", interaction.user.id, role_id, guild_id);
+
+            format!("Роль <@&{}> больше не выдается за силу шамана", role_id)
+        },
+        "list" => render_roles(&settings.rank_roles),
+        "sync" => {
+            if settings.rank_roles.is_empty() {
+                return Ok(utils::format_error("Роли рангов не настроены, добавь их командой `/sign_roles set`"));
+            }
+
+            let stats = roles::sync_guild(dao.as_ref(), ctx.http(), &settings).await?;
+
+            info!("User {} synced rank roles in guild {}", interaction.user.id, guild_id);
+
+            if stats.failed > 0 {
+                format!(
+                    "Роли обновлены у {} участников, у {} не удалось: проверь, что у бота есть право управлять ролями и его роль выше ролей рангов",
+                    stats.changed, stats.failed
+                )
+            } else {
+                format!("Роли обновлены у {} участников", stats.changed)
+            }
+        },
+        cmd => return Err(anyhow!(format!("Unknown roles subcommand {}", cmd))),
+    };
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true)
+    ))
+}
+
+fn render_roles(rank_roles: &RankRoles) -> String {
+    if rank_roles.is_empty() {
+        return "Роли рангов не настроены".to_string();
+    }
+
+    let list = rank_roles.0.iter()
+        .map(|r| format!("<@&{}>: сила шамана от {}", r.role_id, r.min_power))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    formatdoc!(r#"
+        __**Роли рангов**__
+        {}
+        *Игрок получает роль наивысшего достигнутого ранга*"#,
+        list
+    )
+}
+
+pub fn register() -> CreateCommand {
+    let role = || CreateCommandOption::new(CommandOptionType::Role, "role", "Discord role")
+        .required(true);
+
+    CreateCommand::new("sign_roles")
+        .description("Sync Discord roles with shaman ranks (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD | Permissions::MANAGE_ROLES)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "set", "Give role to shamans with power not less than given")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "power", "Lowest shaman power of rank")
+                        .required(true)
+                        .min_int_value(0)
+                        .max_int_value(100)
+                )
+                .add_sub_option(role())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "Stop giving role for shaman power")
+                .add_sub_option(role())
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List rank roles")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "sync", "Give rank roles to all members according to their power")
+        )
+}
@@ -1,9 +1,8 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::info;
-use rand::Rng;
-use serenity::all::{CacheHttp, CommandInteraction, CreateButton, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, ComponentInteraction, CreateActionRow, CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, ChannelId, CreateMessage, Http, ResolvedValue};
 
-use crate::{commands::utils, discord::Handler, signs};
+use crate::{achievements::{self, Event}, commands::{sign_reveal, utils}, db::{Cadence, Dao, GuildInfo, GuildSettings, Scope, SignInfo, SignState}, discord::Handler, events, signs::{self, NextRoll, Pack}, webhooks};
 
 pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
     let user_id = interaction.user.id;
@@ -18,45 +17,314 @@ pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandI
     let guild_id = guild_id.unwrap();
     info!("Rolling sign for user {} from guild {}", user_id, guild_id);
 
-    let mut rand_seq = vec![];
+    let mut choose = false;
+    let mut secret = false;
 
-    for _ in 1..=4 {
-        rand_seq.push(rand::thread_rng().gen_range(1..=4).to_string());
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("choose", ResolvedValue::Boolean(value)) => choose = value,
+            ("secret", ResolvedValue::Boolean(value)) => secret = value,
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
     }
 
-    rand_seq.sort();
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id.get(), interaction.channel_id).await?;
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
 
-    info!("Generated sequance for user {} form guild {} is {:?}", user_id, guild_id, rand_seq);
+    if choose && !utils::is_gm(interaction.member.as_deref(), &settings) {
+        return Ok(utils::format_error("Выбирать из двух знамений по своему желанию может только ГМ"));
+    }
 
-    let sign_id = rand_seq.join("");
+    if secret && !utils::is_gm(interaction.member.as_deref(), &settings) {
+        return Ok(utils::format_error("Скрытое знамение может создать только ГМ"));
+    }
 
-    let dao = handler.dao();
-    let guild = dao.create_sign(guild_id.get(), sign_id.clone(), user_id.get()).await?;
+    if !utils::has_any_role(interaction.member.as_deref(), &settings, &settings.roll_role_ids) {
+        return Ok(utils::format_error("Создавать знамения могут только участники с нужной ролью"));
+    }
 
-    if guild.is_none() {
+    let rolled = roll(dao.as_ref(), scope, user_id.get(), choose, secret).await?;
+
+    if rolled.is_none() {
         info!("Sign for guild {} already exists, skipping request from user {}", guild_id, user_id);
-        return Ok(
-            utils::format_error(
-                "Знамение на сегодня уже создано, приходи завтра"
-            )
-        );
+        return already_created(dao.as_ref(), scope).await;
     }
 
-    let guild = guild.unwrap();
+    let rolled = rolled.unwrap();
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+    let (mut content, embeds, components) = render_rolled(pack, user_id.get(), &rolled, secret);
+
+    if let Rolled::Signs(guild) = &rolled {
+        content.push_str(&roll_achievements(dao.as_ref(), user_id.get(), scope, &guild.signs).await?);
+    }
 
     let msg = CreateInteractionResponse::Message(
         CreateInteractionResponseMessage::new()
-            .content(signs::render_sign(guild.current_sign))
-            .button(
-                CreateButton::new("change_sign")
-                    .style(serenity::all::ButtonStyle::Primary)
-                    .label("Повлиять на знамение")
-                )
+            .content(content)
+            .embeds(embeds)
+            .components(components)
+            .ephemeral(secret)
     );
 
     Ok(msg)
 }
 
+/**
+ * Result of sign roll
+ */
+pub enum Rolled {
+    /// Signs are created
+    Signs(GuildInfo),
+    /// Roller should choose one of two signs with `sign_choose` select
+    Candidates(String, String)
+}
+
+/**
+ * Rolls signs of current session honoring next roll set by previous sign
+ * Secret signs stay hidden from players until GM reveals them
+ * Returns None if signs of current session are already created
+ */
+pub async fn roll(dao: &dyn Dao, scope: Scope, user_id: u64, choose: bool, secret: bool) -> Result<Option<Rolled>> {
+    let next_roll = dao.get_next_roll(scope).await?;
+    let pack = signs::scope_pack(dao, scope).await?;
+
+    if choose || next_roll == Some(NextRoll::ChooseTwo) {
+        if dao.get_guild_info(scope).await?.is_some() {
+            return Ok(None);
+        }
+
+        let first = signs::roll_sign_id();
+        let mut second = signs::roll_sign_id();
+        while second == first {
+            second = signs::roll_sign_id();
+        }
+
+        // Same candidates until one is chosen, so roll cannot be repeated for better ones
+        let (first, second) = dao.save_candidates(scope, (first, second)).await?;
+
+        info!("Candidates for user {} from guild {} are {} and {}", user_id, scope.guild_id, first, second);
+
+        return Ok(Some(Rolled::Candidates(first, second)));
+    }
+
+    let guild = if next_roll == Some(NextRoll::TwoUnmodifiable) {
+        let sign_ids = vec![signs::roll_sign_id(), signs::roll_sign_id()];
+
+        info!("Generated unmodifiable signs for user {} form guild {} are {:?}", user_id, scope.guild_id, sign_ids);
+
+        dao.create_signs(scope, sign_ids, false, secret, user_id, &webhooks::sign_rolled(scope, pack)).await?
+    } else {
+        let sign_id = signs::roll_sign_id();
+
+        info!("Generated sign for user {} form guild {} is {}", user_id, scope.guild_id, sign_id);
+
+        dao.create_signs(scope, vec![sign_id], true, secret, user_id, &webhooks::sign_rolled(scope, pack)).await?
+    };
+
+    if guild.is_none() {
+        return Ok(None);
+    }
+
+    if next_roll.is_some() {
+        dao.set_next_roll(scope, None).await?;
+    }
+
+    let guild = guild.unwrap();
+    events::publish(scope, pack, webhooks::SIGN_ROLLED, &guild.signs);
+
+    Ok(Some(Rolled::Signs(guild)))
+}
+
+/**
+ * Rolls sign when scheduled event starts and posts it to channel from guild settings
+ * Sign is created by bot, so every player can modify it or choose it from candidates
+ */
+pub async fn auto_roll(dao: &dyn Dao, http: &Http, settings: &GuildSettings, bot_id: u64) -> Result<()> {
+    let Some(channel_id) = settings.auto_roll_channel_id else {
+        return Ok(());
+    };
+
+    let rolled = roll(dao, settings.scope(), bot_id, false, false).await?;
+
+    if rolled.is_none() {
+        info!("Sign for guild {} already exists, skipping auto roll", settings.guild_id);
+        return Ok(());
+    }
+
+    let pack = signs::scope_pack(dao, settings.scope()).await?;
+    let (content, embeds, components) = render_rolled(pack, bot_id, &rolled.unwrap(), false);
+
+    ChannelId::new(channel_id).send_message(http, CreateMessage::new()
+        .content(content)
+        .embeds(embeds)
+        .components(components)
+    ).await?;
+
+    Ok(())
+}
+
+/**
+ * Handles choice between two rolled signs
+ * custom_id is `sign_choose:{roller_id}:{first_sign}:{second_sign}`
+ */
+pub async fn run_choose(handler: &Handler, ctx: impl CacheHttp, interaction: &ComponentInteraction, args: &[&str], values: &[String]) -> Result<CreateInteractionResponse> {
+    let user_id = interaction.user.id.get();
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let (roller_id, first, second, secret) = match args {
+        [roller_id, first, second] => (roller_id, first, second, false),
+        [roller_id, first, second, "secret"] => (roller_id, first, second, true),
+        _ => return Err(anyhow!(format!("Wrong sign choice arguments {:?}", args))),
+    };
+    let roller_id: u64 = roller_id.parse()?;
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    if user_id != roller_id && !utils::is_gm(interaction.member.as_ref(), &settings) {
+        // Auto rolled signs have no player behind them, so any player who can roll chooses
+        let rolled_by_bot = roller_id == ctx.http().get_current_user().await?.id.get();
+
+        if !rolled_by_bot {
+            return Ok(utils::format_error("Выбрать знамение может только тот, кто его бросал, или ГМ"));
+        }
+
+        if !utils::has_any_role(interaction.member.as_ref(), &settings, &settings.roll_role_ids) {
+            return Ok(utils::format_error("Выбирать знамения могут только участники с нужной ролью"));
+        }
+    }
+
+    let chosen = values.first().ok_or(anyhow!("Sign is not selected"))?;
+    let discarded = match chosen {
+        s if s == first => second,
+        s if s == second => first,
+        s => return Err(anyhow!(format!("Sign {} is not a candidate", s))),
+    };
+
+    info!("User {} chose sign {} over {} in guild {}", user_id, chosen, discarded, guild_id);
+
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+    let guild = dao.create_signs(scope, vec![chosen.clone()], true, secret, roller_id, &webhooks::sign_rolled(scope, pack)).await?;
+
+    if guild.is_none() {
+        return already_created(dao.as_ref(), scope).await;
+    }
+    let guild = guild.unwrap();
+
+    dao.add_discarded_sign(scope, discarded.to_string(), roller_id).await?;
+    dao.set_next_roll(scope, None).await?;
+    events::publish(scope, pack, webhooks::SIGN_ROLLED, &guild.signs);
+
+    let announcement = roll_achievements(dao.as_ref(), roller_id, scope, &guild.signs).await?;
+    let (mut content, embeds, components) = render_rolled(pack, roller_id, &Rolled::Signs(guild), secret);
+    content.push_str(&announcement);
+
+    Ok(CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .embeds(embeds)
+            .components(components)
+    ))
+}
+
+/**
+ * Announcement of achievements which roller unlocked with rolled signs
+ * Hidden signs are not counted until GM reveals them, so achievements do not give them away
+ */
+pub async fn roll_achievements<'a>(dao: &dyn Dao, user_id: u64, scope: Scope, signs: impl IntoIterator<Item = &'a SignInfo>) -> Result<String> {
+    let sign_ids = signs.into_iter()
+        .filter(|s| !s.hidden)
+        .map(|s| s.id.clone())
+        .collect::<Vec<_>>();
+
+    if sign_ids.is_empty() {
+        return Ok(String::new());
+    }
+
+    let unlocked = achievements::check(dao, user_id, scope, Event::Rolled { sign_ids: &sign_ids }).await?;
+
+    Ok(achievements::render_unlocked(&unlocked))
+}
+
+/**
+ * Error for second roll in one session, tells when next sign can be rolled
+ */
+async fn already_created(dao: &dyn Dao, scope: Scope) -> Result<CreateInteractionResponse> {
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    Ok(utils::format_error(match settings.cadence {
+        Cadence::Daily => "Знамение на сегодня уже создано, приходи завтра",
+        Cadence::Manual => "Знамение на эту сессию уже создано, дождись следующей",
+        Cadence::ScheduledEvents => "Знамение на эту сессию уже создано, дождись следующего события",
+    }))
+}
+
+/**
+ * Secret roll is shown only to GM, so hidden signs are rendered with reveal button
+ */
+fn render_rolled(pack: &Pack, roller_id: u64, rolled: &Rolled, secret: bool) -> (String, Vec<CreateEmbed>, Vec<CreateActionRow>) {
+    match rolled {
+        Rolled::Signs(guild) => {
+            let (content, embeds) = utils::render_signs(pack, &guild.signs, secret);
+            let mut components = utils::sign_buttons(pack, &guild.signs, false);
+
+            if guild.signs.iter().any(|s| s.hidden) {
+                components.push(sign_reveal::reveal_button());
+            }
+
+            (content, embeds, components)
+        },
+        Rolled::Candidates(first, second) => render_candidates(pack, roller_id, first, second, secret),
+    }
+}
+
+fn render_candidates(pack: &Pack, roller_id: u64, first: &str, second: &str, secret: bool) -> (String, Vec<CreateEmbed>, Vec<CreateActionRow>) {
+    let embeds = [first, second].iter()
+        .map(|id| CreateEmbed::new().description(pack.render_sign(&SignInfo {
+            row_id: 0,
+            id: id.to_string(),
+            created_by_user_id: roller_id,
+            state: SignState::Created,
+            created_at: std::time::SystemTime::now(),
+            modifiable: true,
+            hidden: false,
+        })))
+        .collect();
+
+    let options = [first, second].iter()
+        .map(|id| CreateSelectMenuOption::new(format!("{} — {}", id, pack.get_name(id)), id.to_string()))
+        .collect();
+
+    (
+        format!("<@{}> выпало два знамения, какое из них случится?", roller_id),
+        embeds,
+        vec![CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                format!("sign_choose:{}:{}:{}{}", roller_id, first, second, if secret {":secret"} else {""}),
+                CreateSelectMenuKind::String { options }
+            )
+                .placeholder("Выбрать знамение")
+        )]
+    )
+}
+
 pub fn register() -> CreateCommand {
-    CreateCommand::new("sign_roll").description("Roll enoa sign")
-}
\ No newline at end of file
+    CreateCommand::new("sign_roll")
+        .description("Roll enoa sign")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Boolean, "choose", "Roll two signs and choose one of them (GM only)")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Boolean, "secret", "Roll hidden sign and reveal it later (GM only)")
+        )
+}
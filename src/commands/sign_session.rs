@@ -0,0 +1,41 @@
+use anyhow::Result;
+use log::info;
+use serenity::all::{CacheHttp, CommandInteraction, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions};
+
+use crate::{commands::utils, db::{Cadence, GuildSettings}, discord::Handler};
+
+/**
+ * GM command to start new game session, so new sign can be rolled
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    if settings.cadence != Cadence::Manual {
+        return Ok(utils::format_error("Сессии начинаются вручную только при настройке cadence: manual"));
+    }
+
+    let session = dao.start_session(scope, None).await?;
+
+    info!("User {} started session {} in guild {}", interaction.user.id, session.id, guild_id);
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content("__**Началась новая сессия**__\nМожно создать новое знамение")
+    ))
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_session")
+        .description("Start new game session with new sign (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+}
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::info;
+use serenity::all::{CacheHttp, ChannelType, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions, ResolvedValue};
+
+use crate::{commands::{sign_setup, utils}, db::GuildSettings, discord::Handler, signs::{self, Pack}};
+
+/**
+ * Shows settings of guild or campaign bound to channel, changing ones passed as options
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let mut settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    let options = interaction.data.options();
+
+    for option in &options {
+        match (option.name, &option.value) {
+            ("roll_mode", ResolvedValue::String(value)) => settings.modify_roll_mode = value.parse()?,
+            ("assist_max_helpers", ResolvedValue::Integer(value)) => settings.assist_max_helpers = (*value).try_into()?,
+            ("assist_bonus", ResolvedValue::Integer(value)) => settings.assist_bonus = (*value).try_into()?,
+            ("assist_timeout", ResolvedValue::Integer(value)) => settings.assist_timeout_secs = (*value).try_into()?,
+            ("cadence", ResolvedValue::String(value)) => settings.cadence = value.parse()?,
+            ("auto_roll_channel", ResolvedValue::Channel(channel)) => settings.auto_roll_channel_id = Some(channel.id.get()),
+            ("auto_roll_event_name", ResolvedValue::String(value)) => settings.auto_roll_event_name = Some(value.to_string()),
+            ("auto_roll_event_channel", ResolvedValue::Channel(channel)) => settings.auto_roll_event_channel_id = Some(channel.id.get()),
+            ("auto_roll_reset", ResolvedValue::Boolean(value)) => if *value {
+                settings.auto_roll_channel_id = None;
+                settings.auto_roll_event_name = None;
+                settings.auto_roll_event_channel_id = None;
+            },
+            ("power_min", ResolvedValue::Integer(value)) => settings.power_min = (*value).try_into()?,
+            ("power_max", ResolvedValue::Integer(value)) => settings.power_max = (*value).try_into()?,
+            ("power_baseline", ResolvedValue::Integer(value)) => settings.power_baseline = (*value).try_into()?,
+            ("power_decay", ResolvedValue::Integer(value)) => settings.power_decay = (*value).try_into()?,
+            ("modify_role", ResolvedValue::Role(role)) => add_role(&mut settings.modify_role_ids, role.id.get()),
+            ("roll_role", ResolvedValue::Role(role)) => add_role(&mut settings.roll_role_ids, role.id.get()),
+            ("roles_reset", ResolvedValue::Boolean(value)) => if *value {
+                settings.modify_role_ids.clear();
+                settings.roll_role_ids.clear();
+            },
+            ("ranks", ResolvedValue::String(value)) => match value.parse() {
+                Ok(ranks) => settings.ranks = ranks,
+                Err(_) => return Ok(utils::format_error("Ранги задаются так: `0:Ученик,10:Шаман,16:Верховный шаман`")),
+            },
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    if settings.power_min > settings.power_baseline || settings.power_baseline > settings.power_max {
+        return Ok(utils::format_error("Базовая сила шамана должна быть между минимальной и максимальной"));
+    }
+
+    if !options.is_empty() {
+        info!("User {} changes settings of guild {}: {:?}", interaction.user.id, guild_id, settings);
+        dao.save_guild_settings(settings.clone()).await?;
+    }
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(render_settings(&settings, signs::scope_pack(dao.as_ref(), scope).await?))
+            .ephemeral(true)
+    ))
+}
+
+fn render_settings(settings: &GuildSettings, pack: &Pack) -> String {
+    formatdoc!(r#"
+        __**Настройки {}**__
+        **Бросок на изменение знамения:** {}
+        **Помощников в ритуале:** {}
+        **Бонус за помощника:** +{}
+        **Длительность ритуала:** {} сек.
+        **Колода знамений:** {}
+        **Часовой пояс:** {}
+        **Роль ГМ:** {}
+        **Новое знамение:** {}
+        **Автоматический бросок:** {}
+        **Фильтр событий:** {}
+        **Сила шамана:** от {} до {}
+        **Возврат силы:** {}
+        **Ранги:** {}
+        **Создавать знамения могут:** {}
+        **Влиять на знамения могут:** {}
+        "#,
+        if settings.campaign_id == 0 {"сервера"} else {"кампании"},
+        settings.modify_roll_mode,
+        if settings.assist_max_helpers > 0 {
+            settings.assist_max_helpers.to_string()
+        } else {
+            "ритуал отключен".to_string()
+        },
+        settings.assist_bonus,
+        settings.assist_timeout_secs,
+        pack.name,
+        settings.timezone.as_deref().unwrap_or("время сервера бота"),
+        settings.gm_role_id.map(|r| format!("<@&{}>", r)).unwrap_or("только управляющие сервером".to_string()),
+        sign_setup::cadence_name(settings.cadence),
+        match settings.auto_roll_channel_id {
+            Some(channel_id) => format!("в <#{}> при начале события", channel_id),
+            None => "отключен".to_string(),
+        },
+        render_event_filter(settings),
+        settings.power_min,
+        settings.power_max,
+        if settings.power_decay > 0 {
+            format!("на {} в день к {}", settings.power_decay, settings.power_baseline)
+        } else {
+            "отключен".to_string()
+        },
+        render_ranks(settings),
+        render_roles(&settings.roll_role_ids),
+        render_roles(&settings.modify_role_ids)
+    )
+}
+
+fn add_role(role_ids: &mut Vec<u64>, role_id: u64) {
+    if !role_ids.contains(&role_id) {
+        role_ids.push(role_id);
+    }
+}
+
+fn render_roles(role_ids: &[u64]) -> String {
+    if role_ids.is_empty() {
+        return "все".to_string();
+    }
+
+    role_ids.iter()
+        .map(|r| format!("<@&{}>", r))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn render_ranks(settings: &GuildSettings) -> String {
+    if settings.ranks.0.is_empty() {
+        return "нет".to_string();
+    }
+
+    settings.ranks.0.iter()
+        .map(|r| format!("{} от {}", r.title, r.min_power))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn render_event_filter(settings: &GuildSettings) -> String {
+    let mut filters = vec![];
+
+    if let Some(name) = &settings.auto_roll_event_name {
+        filters.push(format!("название содержит «{}»", name));
+    }
+    if let Some(channel_id) = settings.auto_roll_event_channel_id {
+        filters.push(format!("проходит в <#{}>", channel_id));
+    }
+
+    if filters.is_empty() {
+        "все события".to_string()
+    } else {
+        filters.join(", ")
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_settings")
+        .description("Show or change sign settings of this guild (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "roll_mode", "How d20 is rolled when modifying sign")
+                .add_string_choice("Normal", "normal")
+                .add_string_choice("Advantage", "advantage")
+                .add_string_choice("Disadvantage", "disadvantage")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "assist_max_helpers", "How many players can help in ritual, 0 to modify sign without ritual")
+                .min_int_value(0)
+                .max_int_value(10)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "assist_bonus", "Roll bonus for every ritual helper")
+                .min_int_value(0)
+                .max_int_value(10)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "assist_timeout", "Seconds before ritual resolves by itself")
+                .min_int_value(10)
+                .max_int_value(3600)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "cadence", "When new sign can be rolled")
+                .add_string_choice("Daily", "daily")
+                .add_string_choice("Manual sessions", "manual")
+                .add_string_choice("Scheduled events", "scheduled_events")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "auto_roll_channel", "Channel to post sign to when scheduled event starts")
+                .channel_types(vec![ChannelType::Text])
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "auto_roll_event_name", "Only events with this text in name start sessions")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "auto_roll_event_channel", "Only events in this channel start sessions")
+                .channel_types(vec![ChannelType::Voice, ChannelType::Stage])
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Boolean, "auto_roll_reset", "Disable auto roll and event filters")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "power_min", "Lowest shaman power")
+                .min_int_value(0)
+                .max_int_value(100)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "power_max", "Highest shaman power")
+                .min_int_value(0)
+                .max_int_value(100)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "power_baseline", "Shaman power regresses toward this value")
+                .min_int_value(0)
+                .max_int_value(100)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "power_decay", "Shaman power change per day toward baseline, 0 to disable")
+                .min_int_value(0)
+                .max_int_value(10)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "ranks", "Shaman ranks like 0:Ученик,10:Шаман,16:Верховный шаман")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Role, "modify_role", "Allow members with this role to modify signs, everyone can by default")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Role, "roll_role", "Allow members with this role to roll signs, everyone can by default")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Boolean, "roles_reset", "Allow everyone to roll and modify signs")
+        )
+}
@@ -0,0 +1,199 @@
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::info;
+use serenity::all::{CacheHttp, ChannelType, CommandInteraction, CommandOptionType, CreateActionRow, CreateButton, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, GuildId, Http, Permissions, ResolvedValue};
+
+use crate::{commands::utils, db::{Cadence, Dao, GuildSettings, Scope}, discord::Handler, setup_page, signs};
+
+/**
+ * Onboarding choices, unset ones are kept as they are
+ */
+#[derive(Debug, Default)]
+pub struct Setup {
+    pub sign_pack: Option<String>,
+    /// Channel where signs are posted
+    pub channel_id: Option<u64>,
+    pub timezone: Option<String>,
+    pub cadence: Option<Cadence>,
+    pub gm_role_id: Option<u64>
+}
+
+/**
+ * Channels and roles of guild which can be chosen during setup
+ */
+#[derive(Debug, Default)]
+pub struct GuildChoices {
+    /// Text channels as id and name, in order of guild
+    pub channels: Vec<(u64, String)>,
+    /// Roles as id and name, highest first
+    pub roles: Vec<(u64, String)>
+}
+
+/**
+ * Loads choices from Discord, so only existing channels and roles of guild can be saved
+ */
+pub async fn guild_choices(http: &Http, guild_id: u64) -> Result<GuildChoices> {
+    let mut channels = http.get_channels(GuildId::new(guild_id)).await?;
+    channels.retain(|c| c.kind == ChannelType::Text);
+    channels.sort_by_key(|c| c.position);
+
+    // Everyone role has id of guild, roles of bots are managed by Discord
+    let mut roles = http.get_guild_roles(GuildId::new(guild_id)).await?;
+    roles.retain(|r| r.id.get() != guild_id && !r.managed);
+    roles.sort_by_key(|r| std::cmp::Reverse(r.position));
+
+    Ok(GuildChoices {
+        channels: channels.into_iter().map(|c| (c.id.get(), c.name)).collect(),
+        roles: roles.into_iter().map(|r| (r.id.get(), r.name)).collect()
+    })
+}
+
+/**
+ * GM command to set up bot in guild, same choices as on web onboarding page
+ */
+pub async fn run(handler: &Handler, ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let mut setup = Setup::default();
+
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("pack", ResolvedValue::String(value)) => setup.sign_pack = Some(value.to_string()),
+            ("channel", ResolvedValue::Channel(channel)) => setup.channel_id = Some(channel.id.get()),
+            ("timezone", ResolvedValue::String(value)) => setup.timezone = Some(value.to_string()),
+            ("schedule", ResolvedValue::String(value)) => setup.cadence = Some(value.parse()?),
+            ("gm_role", ResolvedValue::Role(role)) => setup.gm_role_id = Some(role.id.get()),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let choices = guild_choices(ctx.http(), guild_id).await?;
+
+    let settings = match apply(handler.dao().as_ref(), guild_id, &choices, setup).await? {
+        Ok(settings) => settings,
+        Err(msg) => return Ok(utils::format_error(msg)),
+    };
+
+    info!("User {} set up guild {}", interaction.user.id, guild_id);
+
+    let components = setup_page::link(guild_id, SystemTime::now())
+        .map(|url| vec![CreateActionRow::Buttons(vec![CreateButton::new_link(url).label("Настроить в браузере")])])
+        .unwrap_or_default();
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(render_setup(&settings))
+            .components(components)
+            .ephemeral(true)
+    ))
+}
+
+/**
+ * Validates choices against guild and saves them to settings of guild
+ * Returns message for user if some choice is invalid
+ */
+pub async fn apply(dao: &dyn Dao, guild_id: u64, choices: &GuildChoices, setup: Setup) -> Result<Result<GuildSettings, String>> {
+    let scope = Scope::guild(guild_id);
+    let mut settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+
+    if let Some(pack) = setup.sign_pack {
+        if !signs::has_pack(&pack) {
+            return Ok(Err(format!("Колоды «{}» нет", pack)));
+        }
+        settings.sign_pack = Some(pack);
+    }
+
+    if let Some(timezone) = setup.timezone {
+        let timezone = timezone.trim().to_string();
+
+        if !dao.is_valid_timezone(timezone.clone()).await? {
+            return Ok(Err(format!("Часовой пояс «{}» неизвестен, используйте название вроде Europe/Moscow", timezone)));
+        }
+        settings.timezone = Some(timezone);
+    }
+
+    if let Some(channel_id) = setup.channel_id {
+        if !choices.channels.iter().any(|(id, _)| *id == channel_id) {
+            return Ok(Err("Такого текстового канала на сервере нет".to_string()));
+        }
+        settings.auto_roll_channel_id = Some(channel_id);
+    }
+
+    if let Some(cadence) = setup.cadence {
+        settings.cadence = cadence;
+    }
+
+    if let Some(role_id) = setup.gm_role_id {
+        if !choices.roles.iter().any(|(id, _)| *id == role_id) {
+            return Ok(Err("Такой роли на сервере нет или ее нельзя выдать участникам".to_string()));
+        }
+        settings.gm_role_id = Some(role_id);
+    }
+
+    dao.save_guild_settings(settings.clone()).await?;
+
+    Ok(Ok(settings))
+}
+
+pub fn cadence_name(cadence: Cadence) -> &'static str {
+    match cadence {
+        Cadence::Daily => "каждый день",
+        Cadence::Manual => "когда мастер начинает сессию (/sign_session)",
+        Cadence::ScheduledEvents => "когда начинается событие сервера",
+    }
+}
+
+fn render_setup(settings: &GuildSettings) -> String {
+    formatdoc!(r#"
+        __**Настройка сервера**__
+        **Колода знамений:** {}
+        **Канал знамений:** {}
+        **Часовой пояс:** {}
+        **Новое знамение:** {}
+        **Роль ГМ:** {}
+        "#,
+        signs::get_pack(settings.sign_pack.as_deref()).name,
+        settings.auto_roll_channel_id.map(|c| format!("<#{}>", c)).unwrap_or("не выбран".to_string()),
+        settings.timezone.as_deref().unwrap_or("время сервера бота"),
+        cadence_name(settings.cadence),
+        settings.gm_role_id.map(|r| format!("<@&{}>", r)).unwrap_or("только управляющие сервером".to_string())
+    )
+}
+
+pub fn register() -> CreateCommand {
+    let pack = signs::pack_names().into_iter()
+        .fold(
+            CreateCommandOption::new(CommandOptionType::String, "pack", "Sign pack of guild"),
+            |option, pack| option.add_string_choice(pack, pack)
+        );
+
+    CreateCommand::new("sign_setup")
+        .description("Set up bot in guild (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(pack)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "channel", "Channel to post signs to")
+                .channel_types(vec![ChannelType::Text])
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "timezone", "Timezone where day of daily sign starts, e.g. Europe/Moscow")
+                .max_length(64)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "schedule", "When new sign can be rolled")
+                .add_string_choice("Daily", "daily")
+                .add_string_choice("Manual sessions", "manual")
+                .add_string_choice("Scheduled events", "scheduled_events")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Role, "gm_role", "Members with this role are GMs")
+        )
+}
@@ -0,0 +1,84 @@
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, ResolvedValue};
+
+use crate::{commands::utils, db::{GuildSettings, UserInfo, UserStats}, discord::Handler, power, signs::{self, Pack}};
+
+/**
+ * Shows statistics of player, caller by default
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let mut target = interaction.user.id.get();
+
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("user", ResolvedValue::User(user, _)) => target = user.id.get(),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+    let settings = dao.get_guild_settings(scope).await?
+        .unwrap_or(GuildSettings::new(scope));
+    let mut user_info = dao.get_user_info(target, scope).await?
+        .unwrap_or(UserInfo::new(target, scope));
+    power::regress(&mut user_info, &settings, SystemTime::now());
+
+    let stats = dao.get_user_stats(target, scope).await?;
+    let pack = signs::scope_pack(dao.as_ref(), scope).await?;
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(render_stats(pack, &user_info, &stats, &settings))
+            .ephemeral(true)
+    ))
+}
+
+fn render_stats(pack: &Pack, user_info: &UserInfo, stats: &UserStats, settings: &GuildSettings) -> String {
+    let success_rate = if stats.modify_attempts > 0 {
+        format!("{} ({}%)", stats.modify_successes, stats.modify_successes * 100 / stats.modify_attempts)
+    } else {
+        "0".to_string()
+    };
+    let peak_power = stats.peak_power
+        .map_or(user_info.shaman_power, |p| p.max(user_info.shaman_power));
+
+    formatdoc!(r#"
+        __**Статистика {}**__
+        **Создано знамений:** {}
+        **Попыток повлиять:** {}
+        **Удачных попыток:** {}
+        **Средний d20:** {}
+        **Сила шамана:** {}, наибольшая {}
+        **Чаще всего выпадало:** {}"#,
+        utils::player_mention(user_info),
+        stats.signs_rolled,
+        stats.modify_attempts,
+        success_rate,
+        stats.average_d20.map_or("—".to_string(), |d| format!("{:.1}", d)),
+        power::render_power(user_info.shaman_power, settings),
+        peak_power,
+        match &stats.most_frequent_sign {
+            Some((sign_id, count)) => format!("{} ({} раз)", pack.get_name(sign_id), count),
+            None => "—".to_string(),
+        }
+    )
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_stats")
+        .description("Show sign statistics of player")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "Player, you by default")
+        )
+}
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use indoc::formatdoc;
+use log::info;
+use serenity::all::{CacheHttp, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions, ResolvedOption, ResolvedValue};
+
+use crate::{commands::utils, db::Webhook, discord::Handler};
+
+/**
+ * GM command to manage outgoing webhooks notified about signs of guild or campaign
+ */
+pub async fn run(handler: &Handler, _ctx: impl CacheHttp, interaction: &CommandInteraction) -> Result<CreateInteractionResponse> {
+    let guild_id = interaction.guild_id;
+
+    if guild_id.is_none() {
+        return Ok(utils::format_error("Мне можно написать только с сервера."));
+    }
+    let guild_id = guild_id.unwrap().get();
+
+    let options = interaction.data.options();
+    let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(options), .. }) = options.first() else {
+        return Err(anyhow!("Webhook subcommand is not set"));
+    };
+
+    let mut url = None;
+    let mut secret = None;
+    let mut id = None;
+
+    for option in options {
+        match (option.name, &option.value) {
+            ("url", ResolvedValue::String(value)) => url = Some(value.trim().to_string()),
+            ("secret", ResolvedValue::String(value)) => secret = Some(value.to_string()),
+            ("id", ResolvedValue::Integer(value)) => id = Some(*value),
+            (name, _) => return Err(anyhow!(format!("Unknown option {}", name))),
+        }
+    }
+
+    let dao = handler.dao();
+    let scope = utils::resolve_scope(dao.as_ref(), guild_id, interaction.channel_id).await?;
+
+    let content = match *subcommand {
+        "add" => {
+            let url = url.ok_or(anyhow!("Url option is not set"))?;
+
+            if !url.starts_with("https://") {
+                return Ok(utils::format_error("Адрес вебхука должен начинаться с https://"));
+            }
+
+            let secret = secret.unwrap_or_else(utils::random_token);
+            let webhook = dao.create_webhook(scope, url.clone(), secret.clone()).await?;
+
+            let Some(webhook) = webhook else {
+                return Ok(utils::format_error("Такой вебхук уже есть"));
+            };
+
+            info!("User {} added webhook {} in guild {}", interaction.user.id, webhook.id, guild_id);
+
+            formatdoc!(r#"
+                Вебхук {} добавлен: {}
+                Секрет подписи: `{}`
+                *Подпись HMAC-SHA256 тела запроса приходит в заголовке `X-Sign-Signature`*"#,
+                webhook.id, webhook.url, secret
+            )
+        },
+        "remove" => {
+            let id = id.ok_or(anyhow!("Id option is not set"))?;
+
+            if !dao.delete_webhook(scope, id).await? {
+                return Ok(utils::format_error(format!("Вебхука {} нет", id)));
+            }
+
+            info!("User {} removed webhook {} in guild {}", interaction.user.id, id, guild_id);
+
+            format!("Вебхук {} удален", id)
+        },
+        "list" => render_webhooks(&dao.get_webhooks(scope).await?),
+        cmd => return Err(anyhow!(format!("Unknown webhook subcommand {}", cmd))),
+    };
+
+    Ok(CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true)
+    ))
+}
+
+fn render_webhooks(webhooks: &[Webhook]) -> String {
+    if webhooks.is_empty() {
+        return "Вебхуков нет".to_string();
+    }
+
+    let list = webhooks.iter()
+        .map(|w| format!("**{}**: {}", w.id, w.url))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("__**Вебхуки**__\n{}", list)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sign_webhook")
+        .description("Manage webhooks notified about signs (GM only)")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "add", "Add webhook")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "url", "Address to POST sign events to")
+                        .required(true)
+                        .max_length(500)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "secret", "Key of payload signature, generated by default")
+                        .min_length(16)
+                        .max_length(200)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "Remove webhook")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "id", "Webhook id from list")
+                        .required(true)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List webhooks")
+        )
+}
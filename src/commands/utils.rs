@@ -1,4 +1,8 @@
-use serenity::all::{CreateInteractionResponse, CreateInteractionResponseMessage};
+use anyhow::Result;
+use rand::RngCore;
+use serenity::all::{ButtonStyle, ChannelId, CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, Member};
+
+use crate::{db::{Dao, GuildSettings, Scope, SignInfo, SignState, UserInfo}, signs::Pack};
 
 
 pub fn format_error(msg: impl Into<String>) -> CreateInteractionResponse {
@@ -10,4 +14,119 @@ pub fn format_error(msg: impl Into<String>) -> CreateInteractionResponse {
             .content(new_msg)
             .ephemeral(true)
     )
-}
\ No newline at end of file
+}
+
+/**
+ * Splits component custom_id into name and arguments
+ * `sign_pack:next:2` -> (`sign_pack`, [`next`, `2`])
+ */
+pub fn parse_custom_id(custom_id: &str) -> (&str, Vec<&str>) {
+    let mut parts = custom_id.split(':');
+    let name = parts.next().unwrap_or_default();
+
+    (name, parts.collect())
+}
+
+/**
+ * Buttons attached to rolled signs message, one row for every modifiable sign which is not hidden
+ * Button of already modified sign is disabled
+ * custom_id is `change_sign:{normal|luck}:{sign_row_id}`
+ */
+pub fn sign_buttons(pack: &Pack, signs: &[SignInfo], disabled: bool) -> Vec<CreateActionRow> {
+    let suffix = |sign: &SignInfo| if signs.len() > 1 {
+        format!(" «{}»", pack.get_name(&sign.id))
+    } else {
+        String::new()
+    };
+
+    signs.iter()
+        .filter(|s| s.modifiable && !s.hidden)
+        .map(|s| {
+            let disabled = disabled || s.state != SignState::Created;
+
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(format!("change_sign:normal:{}", s.row_id))
+                    .disabled(disabled)
+                    .style(ButtonStyle::Primary)
+                    .label(format!("Повлиять на знамение{}", suffix(s))),
+                CreateButton::new(format!("change_sign:luck:{}", s.row_id))
+                    .disabled(disabled)
+                    .style(ButtonStyle::Secondary)
+                    .label("Повлиять с Костью Удачи"),
+            ])
+        })
+        .collect()
+}
+
+/**
+ * Renders signs of the day as message content and embeds
+ * Single sign goes to content, several signs are put to embeds to fit message length limit
+ * Hidden signs are rendered only with `show_hidden`, players see just that sign is hidden
+ */
+pub fn render_signs(pack: &Pack, signs: &[SignInfo], show_hidden: bool) -> (String, Vec<CreateEmbed>) {
+    let render = |sign: &SignInfo| if sign.hidden && !show_hidden {
+        "__**Знамение скрыто**__\n*Мастер откроет его позже*\n".to_string()
+    } else {
+        pack.render_sign(sign)
+    };
+
+    if let [sign] = signs {
+        return (render(sign), vec![]);
+    }
+
+    let embeds = signs.iter()
+        .map(|s| CreateEmbed::new().description(render(s)))
+        .collect();
+
+    (format!("__**Знамений сегодня: {}**__", signs.len()), embeds)
+}
+
+/**
+ * GM is a member who can manage guild or has GM role from settings
+ */
+pub fn is_gm(member: Option<&Member>, settings: &GuildSettings) -> bool {
+    let Some(member) = member else {
+        return false;
+    };
+
+    member.permissions.is_some_and(|p| p.manage_guild())
+        || settings.gm_role_id.is_some_and(|r| member.roles.iter().any(|m| m.get() == r))
+}
+
+/**
+ * Checks that member has one of allowed roles, GM and everyone when no roles are set are allowed
+ */
+pub fn has_any_role(member: Option<&Member>, settings: &GuildSettings, role_ids: &[u64]) -> bool {
+    if role_ids.is_empty() || is_gm(member, settings) {
+        return true;
+    }
+
+    member.is_some_and(|m| m.roles.iter().any(|r| role_ids.contains(&r.get())))
+}
+
+/**
+ * Random hex string for secrets and tokens
+ */
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    hex::encode(bytes)
+}
+
+/**
+ * Mention of player with name of active character
+ */
+pub fn player_mention(user_info: &UserInfo) -> String {
+    match &user_info.active_character_name {
+        Some(name) => format!("<@{}> ({})", user_info.id, name),
+        None => format!("<@{}>", user_info.id),
+    }
+}
+
+/**
+ * Signs of interaction are scoped to campaign bound to its channel, or to whole guild
+ */
+pub async fn resolve_scope(dao: &dyn Dao, guild_id: u64, channel_id: ChannelId) -> Result<Scope> {
+    dao.get_channel_scope(guild_id, channel_id.get()).await
+}
@@ -1,32 +1,74 @@
 use serde::Deserialize;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
+    /// Comma separated files of sign packs, the first one is default pack
     sign_pack_path: String,
+    achievements_path: Option<String>,
     pg: deadpool_postgres::Config,
     discord_token: String,
     application_id: u64,
-    server: Option<ServerConf>
+    server: Option<ServerConf>,
+    /// HTTP endpoint when server is configured, gateway otherwise
+    interaction_source: Option<InteractionSource>,
+    /// Gateway delivers guild joins and scheduled events, enabled by default
+    gateway_enabled: Option<bool>
+}
+
+/**
+ * Where bot gets interactions from, other source only delivers events
+ * Should match Interactions Endpoint URL of Discord application
+ */
+#[derive(Debug, Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionSource {
+    Gateway,
+    Http
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerConf {
     address: String,
     discord_pk: String,
+    /// Address of server as players see it, pages are served only when it is set
+    public_url: Option<String>,
+    /// Key to sign links and OAuth state, required with `public_url`
+    secret: Option<String>,
+    /// Secret of Discord application, bot installation works only when it is set
+    client_secret: Option<String>,
+    /// Discord API address, can be replaced by local stub
+    discord_api_url: Option<String>,
 }
 
 impl AppConfig {
     pub fn from_env() -> Result<AppConfig> {
-        Ok(config::Config::builder()
+        let config: AppConfig = config::Config::builder()
             .add_source(config::Environment::default().separator("__"))
             .build()?
-            .try_deserialize()?)
+            .try_deserialize()?;
+
+        match config.interaction_source() {
+            InteractionSource::Http if config.server.is_none() =>
+                return Err(anyhow!("Server should be configured to get interactions over HTTP")),
+            InteractionSource::Gateway if !config.gateway_enabled() =>
+                return Err(anyhow!("Gateway should be enabled to get interactions from it")),
+            _ => {},
+        }
+
+        Ok(config)
     }
 
-    pub fn sign_pack_path(&self) -> String {
-        self.sign_pack_path.clone()
+    pub fn sign_pack_paths(&self) -> Vec<String> {
+        self.sign_pack_path.split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect()
+    }
+
+    pub fn achievements_path(&self) -> Option<String> {
+        self.achievements_path.clone()
     }
 
     pub fn pg(&self) -> &deadpool_postgres::Config {
@@ -44,6 +86,17 @@ impl AppConfig {
     pub fn server(&self) -> &Option<ServerConf> {
         &self.server
     }
+
+    pub fn interaction_source(&self) -> InteractionSource {
+        self.interaction_source.unwrap_or(match self.server {
+            Some(_) => InteractionSource::Http,
+            None => InteractionSource::Gateway,
+        })
+    }
+
+    pub fn gateway_enabled(&self) -> bool {
+        self.gateway_enabled.unwrap_or(true)
+    }
 }
 
 impl ServerConf {
@@ -54,4 +107,20 @@ impl ServerConf {
     pub fn discord_pk(&self) -> String {
         self.discord_pk.clone()
     }
+
+    pub fn public_url(&self) -> Option<String> {
+        self.public_url.clone()
+    }
+
+    pub fn secret(&self) -> Option<String> {
+        self.secret.clone()
+    }
+
+    pub fn client_secret(&self) -> Option<String> {
+        self.client_secret.clone()
+    }
+
+    pub fn discord_api_url(&self) -> Option<String> {
+        self.discord_api_url.clone()
+    }
 }
\ No newline at end of file
@@ -1,15 +1,73 @@
-use std::time::SystemTime;
+use std::{str::FromStr, time::{Duration, SystemTime}};
 
 use serenity::async_trait;
 use anyhow::Result;
 
+use crate::{dice::RollMode, power::{RankRoles, Ranks}, signs::NextRoll};
+
 pub mod psql;
 
+/**
+ * Part of guild where signs are rolled: whole guild or one of its campaigns
+ * Signs, shaman power, sessions and settings are kept separately for every scope
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Scope {
+    pub guild_id: u64,
+    /// 0 for signs of whole guild
+    pub campaign_id: i64
+}
+
+impl Scope {
+    /**
+     * Scope of channels which are not bound to any campaign
+     */
+    pub fn guild(guild_id: u64) -> Self {
+        Scope { guild_id, campaign_id: 0 }
+    }
+
+    pub fn campaign(guild_id: u64, campaign_id: i64) -> Self {
+        Scope { guild_id, campaign_id }
+    }
+}
+
+/**
+ * Player in scope
+ * When player has active character, `shaman_power` is power of that character
+ */
 #[derive(Debug, Clone)]
 pub struct UserInfo {
     pub id: u64,
     pub guild_id: u64,
-    pub shaman_power: i32
+    pub campaign_id: i64,
+    pub shaman_power: i32,
+    pub luck_dice: i32,
+    pub active_character_id: Option<i64>,
+    pub active_character_name: Option<String>,
+    /// Last time power was changed or regressed toward baseline
+    pub power_updated_at: SystemTime
+}
+
+impl UserInfo {
+    /**
+     * Info for user who never interacted with signs in scope
+     */
+    pub fn new(id: u64, scope: Scope) -> Self {
+        UserInfo {
+            id,
+            guild_id: scope.guild_id,
+            campaign_id: scope.campaign_id,
+            shaman_power: 10,
+            luck_dice: 0,
+            active_character_id: None,
+            active_character_name: None,
+            power_updated_at: SystemTime::now()
+        }
+    }
+
+    pub fn scope(&self) -> Scope {
+        Scope::campaign(self.guild_id, self.campaign_id)
+    }
 }
 
 
@@ -17,37 +75,574 @@ pub struct UserInfo {
 pub enum SignState {
     Created,
     Success{by_user_id: u64},
-    Failed{by_user_id: u64}
+    Failed{by_user_id: u64},
+    CriticalSuccess{by_user_id: u64},
+    CriticalFailure{by_user_id: u64}
 }
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct SignInfo {
+    /// Key of this exact roll in storage, while `id` is sign id from pack
+    pub row_id: i64,
     pub id: String,
     pub created_by_user_id: u64,
     pub state: SignState,
     pub created_at: SystemTime,
+    pub modifiable: bool,
+    /// Secret sign is visible only to GM until revealed
+    pub hidden: bool,
+}
+
+/**
+ * Sign from guild history, including discarded candidates which never became active
+ */
+#[derive(Debug)]
+pub struct HistoryEntry {
+    pub sign: SignInfo,
+    pub discarded: bool
 }
 
 pub struct GuildInfo {
     pub guild_id: u64,
-    pub current_sign: SignInfo
+    pub campaign_id: i64,
+    /// Active signs of the day, at least one
+    pub signs: Vec<SignInfo>
+}
+
+impl GuildInfo {
+    /**
+     * Sign which can still be modified, if any
+     */
+    pub fn first_modifiable(&self) -> Option<&SignInfo> {
+        self.signs.iter().find(|s| s.modifiable && !s.hidden && s.state == SignState::Created)
+    }
+
+    pub fn get_sign(&self, row_id: i64) -> Option<&SignInfo> {
+        self.signs.iter().find(|s| s.row_id == row_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GuildSettings {
+    pub guild_id: u64,
+    pub campaign_id: i64,
+    pub modify_roll_mode: RollMode,
+    pub assist_max_helpers: i32,
+    pub assist_bonus: i32,
+    pub assist_timeout_secs: i32,
+    pub cadence: Cadence,
+    /// Channel to post sign to when scheduled event starts
+    pub auto_roll_channel_id: Option<u64>,
+    /// Only events which name contains this text start sessions
+    pub auto_roll_event_name: Option<String>,
+    /// Only events in this channel start sessions
+    pub auto_roll_event_channel_id: Option<u64>,
+    pub power_min: i32,
+    pub power_max: i32,
+    /// Power regresses toward baseline when decay is set
+    pub power_baseline: i32,
+    /// Power change per day toward baseline, 0 disables regression
+    pub power_decay: i32,
+    pub ranks: Ranks,
+    /// Discord roles synced with shaman power of players
+    pub rank_roles: RankRoles,
+    /// Only members with one of these roles can modify signs, everyone if empty
+    pub modify_role_ids: Vec<u64>,
+    /// Only members with one of these roles can roll signs, everyone if empty
+    pub roll_role_ids: Vec<u64>,
+    // Fields below are chosen during setup for whole guild, settings of campaign have them from guild
+    /// Pack chosen during setup, campaigns without own pack use it too
+    pub sign_pack: Option<String>,
+    /// IANA name of timezone where daily sign changes, server timezone if not set
+    pub timezone: Option<String>,
+    /// Members with this role are GMs along with those who can manage guild
+    pub gm_role_id: Option<u64>
+}
+
+impl GuildSettings {
+    /**
+     * Settings for guild or campaign which was never configured
+     */
+    pub fn new(scope: Scope) -> Self {
+        GuildSettings {
+            guild_id: scope.guild_id,
+            campaign_id: scope.campaign_id,
+            modify_roll_mode: RollMode::Normal,
+            assist_max_helpers: 0,
+            assist_bonus: 1,
+            assist_timeout_secs: 60,
+            cadence: Cadence::Daily,
+            auto_roll_channel_id: None,
+            auto_roll_event_name: None,
+            auto_roll_event_channel_id: None,
+            power_min: 1,
+            power_max: 20,
+            power_baseline: 10,
+            power_decay: 0,
+            ranks: Ranks::default(),
+            rank_roles: RankRoles::default(),
+            modify_role_ids: vec![],
+            roll_role_ids: vec![],
+            sign_pack: None,
+            timezone: None,
+            gm_role_id: None
+        }
+    }
+
+    pub fn scope(&self) -> Scope {
+        Scope::campaign(self.guild_id, self.campaign_id)
+    }
+
+    /**
+     * Checks scheduled event against event filters, event matches if filter is not set
+     */
+    pub fn event_matches(&self, name: &str, channel_id: Option<u64>) -> bool {
+        let name_matches = self.auto_roll_event_name.as_ref()
+            .is_none_or(|n| name.to_lowercase().contains(&n.to_lowercase()));
+        let channel_matches = self.auto_roll_event_channel_id
+            .is_none_or(|c| channel_id == Some(c));
+
+        name_matches && channel_matches
+    }
+}
+
+/**
+ * When new sign can be rolled
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Cadence {
+    /// Every calendar day
+    Daily,
+    /// When GM starts new session with command
+    Manual,
+    /// When Discord scheduled event of guild starts
+    ScheduledEvents
+}
+
+impl Cadence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cadence::Daily => "daily",
+            Cadence::Manual => "manual",
+            Cadence::ScheduledEvents => "scheduled_events",
+        }
+    }
+}
+
+impl FromStr for Cadence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Cadence::Daily),
+            "manual" => Ok(Cadence::Manual),
+            "scheduled_events" => Ok(Cadence::ScheduledEvents),
+            s => Err(anyhow::anyhow!(format!("Unknown cadence {}", s))),
+        }
+    }
+}
+
+/**
+ * Game session, all signs of the session are active at once
+ */
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: i64,
+    pub guild_id: u64,
+    pub campaign_id: i64,
+    pub started_at: SystemTime,
+    /// Scheduled event which started session
+    pub event_id: Option<u64>
+}
+
+/**
+ * Pending group ritual to modify sign
+ */
+#[derive(Debug, Clone)]
+pub struct Ritual {
+    pub guild_id: u64,
+    pub campaign_id: i64,
+    pub sign_row_id: i64,
+    pub shaman_id: u64,
+    pub use_luck: bool,
+    pub channel_id: u64,
+    /// Message with ritual buttons, they are disabled when ritual ends
+    pub message_id: Option<u64>,
+    pub helpers: Vec<u64>,
+    pub expires_at: SystemTime
+}
+
+impl Ritual {
+    pub fn scope(&self) -> Scope {
+        Scope::campaign(self.guild_id, self.campaign_id)
+    }
+}
+
+/**
+ * Player character with its own shaman power
+ */
+#[derive(Debug, Clone)]
+pub struct Character {
+    pub id: i64,
+    pub owner_id: u64,
+    pub name: String,
+    pub shaman_power: i32
+}
+
+/**
+ * Campaign played in some channels of guild, has its own signs, shaman power and settings
+ */
+#[derive(Debug, Clone)]
+pub struct Campaign {
+    pub id: i64,
+    pub guild_id: u64,
+    pub name: String,
+    /// Name of sign pack used by campaign, pack of guild if not set
+    pub pack: Option<String>,
+    pub channels: Vec<u64>
+}
+
+impl Campaign {
+    pub fn scope(&self) -> Scope {
+        Scope::campaign(self.guild_id, self.id)
+    }
+}
+
+/**
+ * Roll of player who modified sign
+ */
+#[derive(Debug, Clone)]
+pub struct ModifyRoll {
+    pub user_id: u64,
+    pub sign_row_id: i64,
+    /// Natural d20 result
+    pub d20: i32,
+    /// Result with power modifier and ritual bonus
+    pub value: i32,
+    pub success: bool,
+    pub critical: bool,
+    pub power_before: i32,
+    pub power_after: i32
+}
+
+/**
+ * Statistics of player in scope
+ */
+#[derive(Debug, Default)]
+pub struct UserStats {
+    pub signs_rolled: i64,
+    pub modify_attempts: i64,
+    pub modify_successes: i64,
+    pub average_d20: Option<f64>,
+    pub peak_power: Option<i32>,
+    /// Sign id which player rolled most often with number of times
+    pub most_frequent_sign: Option<(String, i64)>
+}
+
+/**
+ * How often sign came up in scope and how its modifications went
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SignStats {
+    pub sign_id: String,
+    pub rolled: i64,
+    pub successes: i64,
+    pub failures: i64
+}
+
+/**
+ * Longest runs of successful and failed modifications in a row
+ */
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Streaks {
+    pub success: i64,
+    pub failure: i64
+}
+
+/**
+ * Achievement unlocked by player, definitions are kept in achievements data file
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnlockedAchievement {
+    pub achievement_id: String,
+    pub unlocked_at: SystemTime
+}
+
+/**
+ * Outgoing HTTP hook notified about sign events of scope
+ */
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub id: i64,
+    pub guild_id: u64,
+    pub campaign_id: i64,
+    pub url: String,
+    /// Key of HMAC signature of payloads
+    pub secret: String
+}
+
+/**
+ * Webhook event to put to outbox of every webhook of scope
+ */
+#[derive(Debug, Clone)]
+pub struct WebhookEvent {
+    pub event_type: String,
+    pub payload: String
+}
+
+/**
+ * Builds webhook events from saved signs
+ * Events are put to outbox in the same transaction as signs, so sign change and its events are saved together
+ */
+pub type WebhookEvents<'a> = &'a (dyn Fn(&[SignInfo]) -> Result<Vec<WebhookEvent>> + Send + Sync);
+
+/**
+ * Webhook event waiting in outbox for delivery
+ */
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub url: String,
+    pub secret: String,
+    pub event_type: String,
+    pub payload: String,
+    /// Failed delivery attempts
+    pub attempts: i32
+}
+
+/**
+ * Token of guild for read-only REST API
+ */
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    pub id: i64,
+    pub guild_id: u64,
+    pub name: String,
+    pub created_by_user_id: u64,
+    pub created_at: SystemTime
 }
 
 #[async_trait]
 pub trait Dao: Sync + Send {
+    /**
+     * Saves shaman power and active character of user
+     * Shaman power is saved to active character if there is one
+     * Luck dice are saved only for new user, they are changed with `add_luck_dice`
+     */
     async fn save_user_info(&self, user_info: UserInfo) -> Result<()>;
-    async fn get_user_info(&self, user_id: u64, guild_id: u64) -> Result<Option<UserInfo>>;
+    async fn get_user_info(&self, user_id: u64, scope: Scope) -> Result<Option<UserInfo>>;
+
+    /**
+     * Atomically gives luck dice to user, negative amount takes them
+     * Returns new amount or None if user has not enough dice, so one die cannot be spent twice
+     */
+    async fn add_luck_dice(&self, user_id: u64, scope: Scope, amount: i32) -> Result<Option<i32>>;
+
+    /**
+     * All players who ever interacted with signs in scope
+     */
+    async fn get_users(&self, scope: Scope) -> Result<Vec<UserInfo>>;
+
+    /**
+     * Create character of user
+     * Returns None if user already has character with this name
+     */
+    async fn create_character(&self, user_id: u64, scope: Scope, name: String) -> Result<Option<Character>>;
+    async fn get_characters(&self, user_id: u64, scope: Scope) -> Result<Vec<Character>>;
+
+    /**
+     * Make character with given name active, or switch back to player own power with None
+     * Returns false if user has no character with this name
+     */
+    async fn set_active_character(&self, user_id: u64, scope: Scope, name: Option<String>) -> Result<bool>;
+
+    /**
+     * Create several signs of current session at once
+     * Session is started if scope has no current session
+     * Returns new GuildInfo or None on conflict (if signs already created in current session)
+     */
+    async fn create_signs(&self, scope: Scope, sign_ids: Vec<String>, modifiable: bool, hidden: bool, sign_created_by: u64, events: WebhookEvents<'_>) -> Result<Option<GuildInfo>>;
+
+    /**
+     * Create sign with given data without webhook events
+     * Returns new GuildInfo or None on conflict (if sign already created in current session)
+     */
+    async fn create_sign(&self, scope: Scope, sign_id: String, sign_created_by: u64) -> Result<Option<GuildInfo>> {
+        self.create_signs(scope, vec![sign_id], true, false, sign_created_by, &|_| Ok(vec![])).await
+    }
+    async fn get_guild_info(&self, scope: Scope) -> Result<Option<GuildInfo>>;
+
+    /**
+     * Change state of one of signs of current session
+     * Returns changed SignInfo or Err with current SignInfo on conflict
+     */
+    async fn change_sign_state(&self, scope: Scope, sign_row_id: i64, new_state: SignState, events: WebhookEvents<'_>) -> Result<Result<SignInfo, Option<SignInfo>>>;
+
+    /**
+     * Record sign which was rolled but not chosen
+     */
+    async fn add_discarded_sign(&self, scope: Scope, sign_id: String, sign_created_by: u64) -> Result<()>;
+
+    /**
+     * Latest signs of scope, newest first
+     */
+    async fn get_sign_history(&self, scope: Scope, limit: i64) -> Result<Vec<HistoryEntry>>;
+
+    async fn add_modify_roll(&self, scope: Scope, roll: ModifyRoll) -> Result<()>;
+
+    /**
+     * Aggregates rolled signs and modify rolls of player, discarded signs are not counted
+     */
+    async fn get_user_stats(&self, user_id: u64, scope: Scope) -> Result<UserStats>;
+
+    /**
+     * Statistics of every sign ever rolled in scope, most frequent first
+     * Discarded signs are not counted
+     */
+    async fn get_sign_stats(&self, scope: Scope) -> Result<Vec<SignStats>>;
+
+    /**
+     * Longest success and failure streaks of sign modifications in scope
+     */
+    async fn get_streaks(&self, scope: Scope) -> Result<Streaks>;
+
+    /**
+     * Successful modifications of player since last failed one
+     */
+    async fn get_success_streak(&self, user_id: u64, scope: Scope) -> Result<i64>;
+
+    /**
+     * Saves achievements of player
+     * Returns ids of achievements which were not unlocked before
+     */
+    async fn unlock_achievements(&self, user_id: u64, scope: Scope, achievement_ids: Vec<String>) -> Result<Vec<String>>;
+
+    /**
+     * Achievements of player, oldest first
+     */
+    async fn get_achievements(&self, user_id: u64, scope: Scope) -> Result<Vec<UnlockedAchievement>>;
+
+    /**
+     * Special rule for next sign roll granted by sign outcome
+     */
+    async fn set_next_roll(&self, scope: Scope, next_roll: Option<NextRoll>) -> Result<()>;
+    async fn get_next_roll(&self, scope: Scope) -> Result<Option<NextRoll>>;
+
+    /**
+     * Saves two signs to choose from unless scope already has pending ones
+     * Returns pending candidates, they are dropped when signs are created
+     */
+    async fn save_candidates(&self, scope: Scope, candidates: (String, String)) -> Result<(String, String)>;
+
+    /**
+     * Reveals all hidden signs of current session
+     * Returns revealed signs
+     */
+    async fn reveal_signs(&self, scope: Scope, events: WebhookEvents<'_>) -> Result<Vec<SignInfo>>;
+
+    /**
+     * Start new session, previous session signs become inactive
+     */
+    async fn start_session(&self, scope: Scope, event_id: Option<u64>) -> Result<Session>;
+
+    /**
+     * Current session according to cadence of scope
+     */
+    async fn get_current_session(&self, scope: Scope) -> Result<Option<Session>>;
+
+    async fn save_guild_settings(&self, settings: GuildSettings) -> Result<()>;
+
+    /**
+     * Settings of scope, campaign without own settings uses settings of guild
+     */
+    async fn get_guild_settings(&self, scope: Scope) -> Result<Option<GuildSettings>>;
+
+    /**
+     * Checks that timezone is known to storage, so daily cadence can use it
+     */
+    async fn is_valid_timezone(&self, timezone: String) -> Result<bool>;
+
+    /**
+     * Start ritual which expires in `timeout_secs`
+     * Returns new Ritual or None if scope already has pending ritual
+     */
+    async fn create_ritual(&self, scope: Scope, sign_row_id: i64, shaman_id: u64, use_luck: bool, channel_id: u64, timeout_secs: i32) -> Result<Option<Ritual>>;
+    async fn get_ritual(&self, scope: Scope) -> Result<Option<Ritual>>;
+    async fn set_ritual_message(&self, scope: Scope, message_id: u64) -> Result<()>;
+
+    /**
+     * Add helper to pending ritual
+     * Returns updated Ritual or None if helper cannot be added (already helps or ritual is full)
+     */
+    async fn add_ritual_helper(&self, scope: Scope, user_id: u64, max_helpers: i32) -> Result<Option<Ritual>>;
+
+    /**
+     * Remove pending ritual, so only one caller can resolve it
+     */
+    async fn take_ritual(&self, scope: Scope) -> Result<Option<Ritual>>;
+    async fn take_expired_rituals(&self) -> Result<Vec<Ritual>>;
+
+    /**
+     * Create campaign in guild
+     * Returns None if guild already has campaign with this name
+     */
+    async fn create_campaign(&self, guild_id: u64, name: String, pack: Option<String>) -> Result<Option<Campaign>>;
+    async fn get_campaigns(&self, guild_id: u64) -> Result<Vec<Campaign>>;
+
+    /**
+     * Bind channel to campaign, channel bound to other campaign is moved
+     * Returns None if there is no campaign with this name
+     */
+    async fn bind_channel(&self, guild_id: u64, campaign_name: String, channel_id: u64) -> Result<Option<Campaign>>;
+
+    /**
+     * Returns false if channel was not bound
+     */
+    async fn unbind_channel(&self, guild_id: u64, channel_id: u64) -> Result<bool>;
+
+    /**
+     * Scope of campaign bound to channel, or scope of whole guild
+     */
+    async fn get_channel_scope(&self, guild_id: u64, channel_id: u64) -> Result<Scope>;
+
+    /**
+     * Name of sign pack chosen for scope, None if default pack is used
+     * Campaign without own pack uses pack of guild
+     */
+    async fn get_sign_pack(&self, scope: Scope) -> Result<Option<String>>;
+
+    /**
+     * Returns None if scope already has webhook with this url
+     */
+    async fn create_webhook(&self, scope: Scope, url: String, secret: String) -> Result<Option<Webhook>>;
+    async fn get_webhooks(&self, scope: Scope) -> Result<Vec<Webhook>>;
+
+    /**
+     * Deletes webhook with its undelivered events
+     */
+    async fn delete_webhook(&self, scope: Scope, id: i64) -> Result<bool>;
+
+    /**
+     * Undelivered events which should be sent now, oldest first
+     */
+    async fn get_due_webhook_events(&self, limit: i64) -> Result<Vec<OutboxEvent>>;
+    async fn mark_webhook_delivered(&self, event_id: i64) -> Result<()>;
+
+    /**
+     * Records failed attempt, event is retried after `retry_in` or never if it is None
+     */
+    async fn mark_webhook_failed(&self, event_id: i64, error: String, retry_in: Option<Duration>) -> Result<()>;
 
     /**
-     * Create sign with given data
-     * Returns new GuildInfo or None on conflict (if sign already created today)
+     * Saves hash of API token
+     * Returns None if guild already has token with this name
      */
-    async fn create_sign(&self, guild_id: u64, sign_id: String, sign_created_by: u64) -> Result<Option<GuildInfo>>;
-    async fn get_guild_info(&self, guild_id: u64) -> Result<Option<GuildInfo>>;
+    async fn create_api_token(&self, guild_id: u64, name: String, token_hash: String, created_by: u64) -> Result<Option<ApiToken>>;
+    async fn get_api_tokens(&self, guild_id: u64) -> Result<Vec<ApiToken>>;
+    async fn delete_api_token(&self, guild_id: u64, name: String) -> Result<bool>;
 
     /**
-     * Change sign state
-     * Returns new GuildInfo or Err with old GuildInfo on conflict
+     * Checks that token with given hash belongs to guild
      */
-    async fn change_sign_state(&self, guild_id: u64, new_state: SignState) -> Result<Result<GuildInfo, Option<GuildInfo>>>;
+    async fn check_api_token(&self, guild_id: u64, token_hash: String) -> Result<bool>;
 }
\ No newline at end of file
@@ -1,13 +1,14 @@
-use chrono::{DateTime, Local};
-use deadpool_postgres::Pool;
+use deadpool_postgres::{Pool, Transaction};
 use anyhow::{anyhow, Result};
 use serenity::async_trait;
-use tokio_postgres::NoTls;
+use tokio_postgres::{types::ToSql, NoTls};
 use crate::db::Dao;
 use anyhow::Context;
-use std::{ops::DerefMut, time::SystemTime};
+use std::{ops::DerefMut, time::Duration};
 
-use super::{GuildInfo, SignInfo, SignState, UserInfo};
+use crate::signs::NextRoll;
+
+use super::{ApiToken, Campaign, Character, GuildInfo, GuildSettings, HistoryEntry, ModifyRoll, OutboxEvent, Ritual, Scope, Session, SignInfo, SignState, SignStats, Streaks, UnlockedAchievement, UserInfo, UserStats, Webhook, WebhookEvent, WebhookEvents};
 
 mod embedded {
     use refinery::embed_migrations;
@@ -25,191 +26,1359 @@ impl Dao for PsqlDao {
         let client = self.pool.get().await
             .with_context(|| "Cannot get connection")?;
 
+        // Player own power is kept as is while character is active
         let stmt = client.prepare(r#"
-            INSERT INTO users (id, guild_id, shaman_power)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (id, guild_id) DO UPDATE
-            SET shaman_power = $3
+            INSERT INTO users (id, guild_id, campaign_id, shaman_power, luck_dice, active_character_id, power_updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (id, guild_id, campaign_id) DO UPDATE
+            SET shaman_power = CASE WHEN $6::bigint IS NULL THEN $4 ELSE users.shaman_power END,
+                power_updated_at = CASE WHEN $6::bigint IS NULL THEN $7 ELSE users.power_updated_at END,
+                active_character_id = $6
         "#).await?;
 
-        client.execute(&stmt, &[&user_info.id.to_string(), &user_info.guild_id.to_string(), &user_info.shaman_power]).await?;
+        client.execute(&stmt, &[
+            &user_info.id.to_string(),
+            &user_info.guild_id.to_string(),
+            &user_info.campaign_id,
+            &user_info.shaman_power,
+            &user_info.luck_dice,
+            &user_info.active_character_id,
+            &user_info.power_updated_at
+        ]).await?;
+
+        if let Some(character_id) = user_info.active_character_id {
+            let stmt = client.prepare(r#"
+                UPDATE characters
+                SET shaman_power = $2, power_updated_at = $3
+                WHERE id = $1
+            "#).await?;
+
+            client.execute(&stmt, &[&character_id, &user_info.shaman_power, &user_info.power_updated_at]).await?;
+        }
 
         Ok(())
     }
 
-    async fn get_user_info(&self, user_id: u64, guild_id: u64) -> Result<Option<UserInfo>> {
+    async fn get_user_info(&self, user_id: u64, scope: Scope) -> Result<Option<UserInfo>> {
         let client = self.pool.get().await
             .with_context(|| "Cannot get connection")?;
 
         let stmt = client.prepare(r#"
-            SELECT id, guild_id, shaman_power 
-            FROM users
-            WHERE id = $1 AND guild_id = $2
+            SELECT u.id, u.guild_id, COALESCE(c.shaman_power, u.shaman_power), u.luck_dice, c.id, c.name,
+                COALESCE(c.power_updated_at, u.power_updated_at)
+            FROM users u
+            LEFT JOIN characters c ON c.id = u.active_character_id
+            WHERE u.id = $1 AND u.guild_id = $2 AND u.campaign_id = $3
         "#).await?;
 
-        let res = client.query_opt(&stmt, &[&user_id.to_string(), &guild_id.to_string()]).await?;
+        let res = client.query_opt(&stmt, &[&user_id.to_string(), &scope.guild_id.to_string(), &scope.campaign_id]).await?;
 
-        if res.is_none() {
-            return Ok(None);
-        }
+        res.map(|row| user_from_row(row, scope)).transpose()
+    }
 
-        let row = res.unwrap();
+    async fn add_luck_dice(&self, user_id: u64, scope: Scope, amount: i32) -> Result<Option<i32>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
 
-        let shaman_power: i32 = row.get(2);
+        let stmt = client.prepare(r#"
+            INSERT INTO users (id, guild_id, campaign_id, shaman_power, luck_dice)
+            VALUES ($1, $2, $3, 10, 0)
+            ON CONFLICT (id, guild_id, campaign_id) DO NOTHING
+        "#).await?;
 
-        Ok(Some(UserInfo {
-            id: user_id,
-            guild_id,
-            shaman_power: shaman_power
-        }))
+        client.execute(&stmt, &[&user_id.to_string(), &scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        // Row is checked and changed in one statement, so concurrent changes are not lost
+        let stmt = client.prepare(r#"
+            UPDATE users
+            SET luck_dice = luck_dice + $4
+            WHERE id = $1 AND guild_id = $2 AND campaign_id = $3 AND luck_dice + $4 >= 0
+            RETURNING luck_dice
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&user_id.to_string(), &scope.guild_id.to_string(), &scope.campaign_id, &amount]).await?;
+
+        Ok(res.map(|row| row.get(0)))
     }
 
-    /**
-     * Create sign with given data
-     * Returns new GuildInfo or None on conflict (if sign already created today)
-     */
-    async fn create_sign(&self, guild_id: u64, sign_id: String, sign_created_by: u64) -> Result<Option<GuildInfo>> {
+    async fn get_users(&self, scope: Scope) -> Result<Vec<UserInfo>> {
         let client = self.pool.get().await
             .with_context(|| "Cannot get connection")?;
 
         let stmt = client.prepare(r#"
-            INSERT INTO guilds (id, sign_id, sign_created_by_id, sign_created_at, sign_state)
-            VALUES ($1, $2, $3, NOW(), $4)
-            ON CONFLICT(id) DO UPDATE
-            SET sign_id = $2, sign_created_by_id = $3, sign_created_at = NOW(), sign_state = $4
-            WHERE guilds.sign_created_at < NOW()::date
-            RETURNING (guilds.sign_created_at)
+            SELECT u.id, u.guild_id, COALESCE(c.shaman_power, u.shaman_power), u.luck_dice, c.id, c.name,
+                COALESCE(c.power_updated_at, u.power_updated_at)
+            FROM users u
+            LEFT JOIN characters c ON c.id = u.active_character_id
+            WHERE u.guild_id = $1 AND u.campaign_id = $2
         "#).await?;
 
-        let res = client.query_opt(&stmt, &[
-                &guild_id.to_string(),
-                &sign_id,
-                &sign_created_by.to_string(),
-                &"Created"
-            ]).await?;
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
 
-        // This query returns smth only if row inserted or updated
-        // So we don't need to select GuildInfo twise
-        if res.is_none() {
-            return Ok(None);
-        }
+        res.into_iter().map(|row| user_from_row(row, scope)).collect()
+    }
 
-        let row = res.unwrap();
+    async fn create_character(&self, user_id: u64, scope: Scope, name: String) -> Result<Option<Character>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
 
-        let created_at: SystemTime = row.get(0);
+        let stmt = client.prepare(r#"
+            INSERT INTO characters (guild_id, campaign_id, owner_id, name, shaman_power)
+            VALUES ($1, $2, $3, $4, 10)
+            ON CONFLICT (guild_id, campaign_id, owner_id, name) DO NOTHING
+            RETURNING id, owner_id, name, shaman_power
+        "#).await?;
 
-        Ok(Some(GuildInfo {
-            guild_id,
-            current_sign: SignInfo {
-                id: sign_id,
-                created_by_user_id: sign_created_by,
-                state: SignState::Created,
-                created_at: created_at
-            },
-        }))
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &user_id.to_string(), &name]).await?;
+
+        res.map(character_from_row).transpose()
     }
 
-    async fn get_guild_info(&self, guild_id: u64) -> Result<Option<GuildInfo>> {
+    async fn get_characters(&self, user_id: u64, scope: Scope) -> Result<Vec<Character>> {
         let client = self.pool.get().await
             .with_context(|| "Cannot get connection")?;
 
         let stmt = client.prepare(r#"
-            SELECT sign_id, sign_created_at, sign_created_by_id, sign_state, sign_state_made_by_id
-            FROM guilds
-            WHERE id = $1
+            SELECT id, owner_id, name, shaman_power
+            FROM characters
+            WHERE guild_id = $1 AND campaign_id = $2 AND owner_id = $3
+            ORDER BY id
         "#).await?;
 
-        let res = client.query_opt(&stmt, &[&guild_id.to_string()]).await?;
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &user_id.to_string()]).await?;
 
-        if res.is_none() {
+        res.into_iter().map(character_from_row).collect()
+    }
+
+    async fn set_active_character(&self, user_id: u64, scope: Scope, name: Option<String>) -> Result<bool> {
+        let mut client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+        let tx = client.transaction().await?;
+
+        let character_id: Option<i64> = match name {
+            Some(name) => {
+                let stmt = tx.prepare(r#"
+                    SELECT id
+                    FROM characters
+                    WHERE guild_id = $1 AND campaign_id = $2 AND owner_id = $3 AND name = $4
+                "#).await?;
+
+                let res = tx.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &user_id.to_string(), &name]).await?;
+
+                match res {
+                    Some(row) => Some(row.get(0)),
+                    None => return Ok(false),
+                }
+            },
+            None => None,
+        };
+
+        let stmt = tx.prepare(r#"
+            INSERT INTO users (id, guild_id, campaign_id, shaman_power, luck_dice, active_character_id)
+            VALUES ($1, $2, $3, 10, 0, $4)
+            ON CONFLICT (id, guild_id, campaign_id) DO UPDATE
+            SET active_character_id = $4
+        "#).await?;
+
+        tx.execute(&stmt, &[&user_id.to_string(), &scope.guild_id.to_string(), &scope.campaign_id, &character_id]).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /**
+     * Create signs with given data in one go
+     * Returns new GuildInfo or None on conflict (if signs already created in current session)
+     */
+    async fn create_signs(&self, scope: Scope, sign_ids: Vec<String>, modifiable: bool, hidden: bool, sign_created_by: u64, events: WebhookEvents<'_>) -> Result<Option<GuildInfo>> {
+        let mut client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+        let tx = client.transaction().await?;
+
+        let guild_id = scope.guild_id.to_string();
+
+        // Guild row is locked until commit, so concurrent rolls cannot both pass the check below
+        // Pending candidates are chosen from only once, they are dropped with created signs
+        let stmt = tx.prepare(r#"
+            INSERT INTO guilds (id, campaign_id)
+            VALUES ($1, $2)
+            ON CONFLICT(id, campaign_id) DO UPDATE
+            SET candidates = NULL
+        "#).await?;
+
+        tx.execute(&stmt, &[&guild_id, &scope.campaign_id]).await?;
+
+        let stmt = tx.prepare(&format!(r#"
+            SELECT {}
+        "#, current_session_sql("$1", "$2"))).await?;
+
+        let session_id: Option<i64> = tx.query_one(&stmt, &[&guild_id, &scope.campaign_id]).await?.get(0);
+
+        // Session is started on first roll if cadence does not start it by itself
+        let session_id = match session_id {
+            Some(id) => id,
+            None => {
+                let stmt = tx.prepare(r#"
+                    INSERT INTO sessions (guild_id, campaign_id, started_at)
+                    VALUES ($1, $2, NOW())
+                    RETURNING id
+                "#).await?;
+
+                tx.query_one(&stmt, &[&guild_id, &scope.campaign_id]).await?.get(0)
+            }
+        };
+
+        let stmt = tx.prepare(r#"
+            SELECT 1
+            FROM signs
+            WHERE session_id = $1 AND NOT discarded
+            LIMIT 1
+        "#).await?;
+
+        if tx.query_opt(&stmt, &[&session_id]).await?.is_some() {
             return Ok(None);
         }
 
-        let row = res.unwrap();
+        let stmt = tx.prepare(r#"
+            INSERT INTO signs (guild_id, campaign_id, sign_id, created_at, created_by_id, state, modifiable, hidden, session_id)
+            VALUES ($1, $2, $3, NOW(), $4, 'Created', $5, $6, $7)
+            RETURNING id, created_at
+        "#).await?;
+
+        let mut signs = vec![];
+
+        for sign_id in sign_ids {
+            let row = tx.query_one(&stmt, &[&guild_id, &scope.campaign_id, &sign_id, &sign_created_by.to_string(), &modifiable, &hidden, &session_id]).await?;
+
+            signs.push(SignInfo {
+                row_id: row.get(0),
+                id: sign_id,
+                created_by_user_id: sign_created_by,
+                state: SignState::Created,
+                created_at: row.get(1),
+                modifiable,
+                hidden
+            });
+        }
+
+        enqueue_webhook_events(&tx, scope, events(&signs)?).await?;
+        tx.commit().await?;
+
+        Ok(Some(GuildInfo {
+            guild_id: scope.guild_id,
+            campaign_id: scope.campaign_id,
+            signs
+        }))
+    }
+
+    async fn get_guild_info(&self, scope: Scope) -> Result<Option<GuildInfo>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
 
-        let sign_id: String = row.get(0);
-        let sign_created_at: SystemTime = row.get(1);
-        let sign_created_by_id: String = row.get(2);
-        let sign_state: String = row.get(3);
-        let sign_state_made_by_id: Option<String> = row.get(4);
+        let stmt = client.prepare(&format!(r#"
+            SELECT id, sign_id, created_at, created_by_id, state, state_made_by_id, modifiable, hidden
+            FROM signs
+            WHERE guild_id = $1 AND campaign_id = $2 AND NOT discarded AND session_id = {}
+            ORDER BY id
+        "#, current_session_sql("$1", "$2"))).await?;
 
-        let dt_local: DateTime<Local> = sign_created_at.clone().into();
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
 
-        if dt_local.date_naive() < chrono::offset::Local::now().date_naive() {
+        if res.is_empty() {
             return Ok(None);
         }
 
         Ok(Some(GuildInfo {
-            guild_id,
-            current_sign: SignInfo {
-                id: sign_id,
-                created_by_user_id: sign_created_by_id.parse()?,
-                state: match sign_state {
-                    s if s == "Created" => SignState::Created,
-                    s if s == "Success" => SignState::Success { by_user_id: sign_state_made_by_id.ok_or(
-                        anyhow!("State changer not set")
-                    )?.parse()? },
-                    s if s == "Failed" => SignState::Failed { by_user_id: sign_state_made_by_id.ok_or(
-                        anyhow!("State changer not set")
-                    )?.parse()? },
-                    _ => unreachable!()
-                },
-                created_at: sign_created_at
-            },
+            guild_id: scope.guild_id,
+            campaign_id: scope.campaign_id,
+            signs: res.into_iter().map(sign_from_row).collect::<Result<_>>()?
         }))
     }
 
     /**
-     * Change sign state
+     * Change state of one of signs of current session
      * New state must not be Created
-     * Returns new GuildInfo or Err with old GuildInfo on conflict
+     * Returns changed SignInfo or Err with current SignInfo on conflict
      */
-    async fn change_sign_state(&self, guild_id: u64, new_state: SignState) -> Result<Result<GuildInfo, Option<GuildInfo>>> {
-        let client = self.pool.get().await
+    async fn change_sign_state(&self, scope: Scope, sign_row_id: i64, new_state: SignState, events: WebhookEvents<'_>) -> Result<Result<SignInfo, Option<SignInfo>>> {
+        let mut client = self.pool.get().await
             .with_context(|| "Cannot get connection")?;
-
-        let stmt = client.prepare(r#"
-            UPDATE guilds
-            SET sign_state = $1, sign_state_made_by_id = $2
-            WHERE id = $3 AND sign_state = 'Created' AND sign_created_at >= NOW()::date
-            RETURNING sign_id, sign_created_at, sign_created_by_id
-        "#).await?;
+        let tx = client.transaction().await?;
 
         let (state, state_made_by) = match new_state {
             SignState::Created => Err(anyhow!("New state canot be Created")),
             SignState::Success { by_user_id } => Ok(("Success", by_user_id.to_string())),
             SignState::Failed { by_user_id } => Ok(("Failed", by_user_id.to_string())),
+            SignState::CriticalSuccess { by_user_id } => Ok(("CriticalSuccess", by_user_id.to_string())),
+            SignState::CriticalFailure { by_user_id } => Ok(("CriticalFailure", by_user_id.to_string())),
         }?;
 
-        let res = client.query_opt(&stmt, &[
+        let stmt = tx.prepare(&format!(r#"
+            UPDATE signs
+            SET state = $1, state_made_by_id = $2
+            WHERE id = $3 AND guild_id = $4 AND campaign_id = $5 AND created_by_id <> $2
+                AND state = 'Created' AND modifiable AND NOT hidden AND NOT discarded AND session_id = {}
+            RETURNING id, sign_id, created_at, created_by_id, state, state_made_by_id, modifiable, hidden
+        "#, current_session_sql("$4", "$5"))).await?;
+
+        let res = tx.query_opt(&stmt, &[
                 &state,
                 &state_made_by,
-                &guild_id.to_string(),
+                &sign_row_id,
+                &scope.guild_id.to_string(),
+                &scope.campaign_id,
             ]).await?;
 
-        if res.is_none() {
-            // In this case sign was not updated
-            // We will just select current state
-            // It can be inconsistent, but i am too lazy to make proper sql request :)
-            return Ok(Err(self.get_guild_info(guild_id).await?));
+        if let Some(row) = res {
+            let sign = sign_from_row(row)?;
+
+            enqueue_webhook_events(&tx, scope, events(std::slice::from_ref(&sign))?).await?;
+            tx.commit().await?;
+
+            return Ok(Ok(sign));
         }
 
-        let row = res.unwrap();
+        // In this case sign was not updated
+        // We will just select current state
+        let stmt = tx.prepare(&format!(r#"
+            SELECT id, sign_id, created_at, created_by_id, state, state_made_by_id, modifiable, hidden
+            FROM signs
+            WHERE id = $1 AND guild_id = $2 AND campaign_id = $3 AND NOT discarded AND session_id = {}
+        "#, current_session_sql("$2", "$3"))).await?;
 
-        let sign_id: String = row.get(0);
-        let sign_created_at: SystemTime = row.get(1);
-        let sign_created_by_id: String = row.get(2);
+        let res = tx.query_opt(&stmt, &[&sign_row_id, &scope.guild_id.to_string(), &scope.campaign_id]).await?;
 
-        Ok(Ok(GuildInfo {
-            guild_id,
-            current_sign: SignInfo {
-                id: sign_id,
-                created_by_user_id: sign_created_by_id.parse()?,
-                state: new_state,
-                created_at: sign_created_at
-            },
-        }))
+        Ok(Err(res.map(sign_from_row).transpose()?))
+    }
+
+    async fn add_discarded_sign(&self, scope: Scope, sign_id: String, sign_created_by: u64) -> Result<()> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(&format!(r#"
+            INSERT INTO signs (guild_id, campaign_id, sign_id, created_at, created_by_id, state, discarded, session_id)
+            VALUES ($1, $2, $3, NOW(), $4, 'Created', true, {})
+        "#, current_session_sql("$1", "$2"))).await?;
+
+        client.execute(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &sign_id, &sign_created_by.to_string()]).await?;
+
+        Ok(())
+    }
+
+    async fn get_sign_history(&self, scope: Scope, limit: i64) -> Result<Vec<HistoryEntry>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT id, sign_id, created_at, created_by_id, state, state_made_by_id, modifiable, hidden, discarded
+            FROM signs
+            WHERE guild_id = $1 AND campaign_id = $2
+            ORDER BY created_at DESC, id DESC
+            LIMIT $3
+        "#).await?;
+
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &limit]).await?;
+
+        res.into_iter().map(|row| {
+            let discarded = row.get(8);
+
+            Ok(HistoryEntry {
+                sign: sign_from_row(row)?,
+                discarded
+            })
+        }).collect()
+    }
+
+    async fn add_modify_roll(&self, scope: Scope, roll: ModifyRoll) -> Result<()> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO modify_rolls (guild_id, campaign_id, user_id, sign_row_id, d20, value, success, critical, power_before, power_after, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW())
+        "#).await?;
+
+        client.execute(&stmt, &[
+            &scope.guild_id.to_string(),
+            &scope.campaign_id,
+            &roll.user_id.to_string(),
+            &roll.sign_row_id,
+            &roll.d20,
+            &roll.value,
+            &roll.success,
+            &roll.critical,
+            &roll.power_before,
+            &roll.power_after
+        ]).await?;
+
+        Ok(())
+    }
+
+    async fn get_user_stats(&self, user_id: u64, scope: Scope) -> Result<UserStats> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let params: [&(dyn ToSql + Sync); 3] = [&scope.guild_id.to_string(), &scope.campaign_id, &user_id.to_string()];
+
+        let stmt = client.prepare(r#"
+            SELECT
+                (
+                    SELECT COUNT(*)
+                    FROM signs
+                    WHERE guild_id = $1 AND campaign_id = $2 AND created_by_id = $3 AND NOT discarded AND NOT hidden
+                ),
+                COUNT(*),
+                COUNT(*) FILTER (WHERE success),
+                AVG(d20)::float8,
+                MAX(GREATEST(power_before, power_after))
+            FROM modify_rolls
+            WHERE guild_id = $1 AND campaign_id = $2 AND user_id = $3
+        "#).await?;
+
+        let row = client.query_one(&stmt, &params).await?;
+
+        let stmt = client.prepare(r#"
+            SELECT sign_id, COUNT(*)
+            FROM signs
+            WHERE guild_id = $1 AND campaign_id = $2 AND created_by_id = $3 AND NOT discarded AND NOT hidden
+            GROUP BY sign_id
+            ORDER BY COUNT(*) DESC, sign_id
+            LIMIT 1
+        "#).await?;
+
+        let most_frequent = client.query_opt(&stmt, &params).await?;
+
+        Ok(UserStats {
+            signs_rolled: row.get(0),
+            modify_attempts: row.get(1),
+            modify_successes: row.get(2),
+            average_d20: row.get(3),
+            peak_power: row.get(4),
+            most_frequent_sign: most_frequent.map(|r| (r.get(0), r.get(1)))
+        })
+    }
+
+    async fn get_sign_stats(&self, scope: Scope) -> Result<Vec<SignStats>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT sign_id,
+                COUNT(*),
+                COUNT(*) FILTER (WHERE state IN ('Success', 'CriticalSuccess')),
+                COUNT(*) FILTER (WHERE state IN ('Failed', 'CriticalFailure'))
+            FROM signs
+            WHERE guild_id = $1 AND campaign_id = $2 AND NOT discarded
+            GROUP BY sign_id
+            ORDER BY COUNT(*) DESC, sign_id
+        "#).await?;
+
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        Ok(res.into_iter().map(|row| SignStats {
+            sign_id: row.get(0),
+            rolled: row.get(1),
+            successes: row.get(2),
+            failures: row.get(3)
+        }).collect())
+    }
+
+    async fn get_streaks(&self, scope: Scope) -> Result<Streaks> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        // Modifications with the same outcome in a row have the same difference of row numbers
+        let stmt = client.prepare(r#"
+            WITH outcomes AS (
+                SELECT id, created_at, state IN ('Success', 'CriticalSuccess') AS success
+                FROM signs
+                WHERE guild_id = $1 AND campaign_id = $2 AND NOT discarded AND state <> 'Created'
+            ),
+            runs AS (
+                SELECT success,
+                    ROW_NUMBER() OVER (ORDER BY created_at, id)
+                        - ROW_NUMBER() OVER (PARTITION BY success ORDER BY created_at, id) AS run
+                FROM outcomes
+            )
+            SELECT success, MAX(len)
+            FROM (
+                SELECT success, run, COUNT(*) AS len
+                FROM runs
+                GROUP BY success, run
+            ) r
+            GROUP BY success
+        "#).await?;
+
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        let mut streaks = Streaks::default();
+        for row in res {
+            let success: bool = row.get(0);
+            if success {
+                streaks.success = row.get(1);
+            } else {
+                streaks.failure = row.get(1);
+            }
+        }
+
+        Ok(streaks)
+    }
+
+    async fn get_success_streak(&self, user_id: u64, scope: Scope) -> Result<i64> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT COUNT(*)
+            FROM modify_rolls
+            WHERE guild_id = $1 AND campaign_id = $2 AND user_id = $3 AND id > COALESCE((
+                SELECT MAX(id)
+                FROM modify_rolls
+                WHERE guild_id = $1 AND campaign_id = $2 AND user_id = $3 AND NOT success
+            ), 0)
+        "#).await?;
+
+        let row = client.query_one(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &user_id.to_string()]).await?;
+
+        Ok(row.get(0))
+    }
+
+    async fn unlock_achievements(&self, user_id: u64, scope: Scope, achievement_ids: Vec<String>) -> Result<Vec<String>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO achievements (guild_id, campaign_id, user_id, achievement_id, unlocked_at)
+            SELECT $1, $2, $3, UNNEST($4::text[]), NOW()
+            ON CONFLICT (guild_id, campaign_id, user_id, achievement_id) DO NOTHING
+            RETURNING achievement_id
+        "#).await?;
+
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &user_id.to_string(), &achievement_ids]).await?;
+
+        Ok(res.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn get_achievements(&self, user_id: u64, scope: Scope) -> Result<Vec<UnlockedAchievement>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT achievement_id, unlocked_at
+            FROM achievements
+            WHERE guild_id = $1 AND campaign_id = $2 AND user_id = $3
+            ORDER BY unlocked_at, achievement_id
+        "#).await?;
+
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &user_id.to_string()]).await?;
+
+        Ok(res.into_iter().map(|row| UnlockedAchievement {
+            achievement_id: row.get(0),
+            unlocked_at: row.get(1)
+        }).collect())
+    }
+
+    async fn set_next_roll(&self, scope: Scope, next_roll: Option<NextRoll>) -> Result<()> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            UPDATE guilds
+            SET next_roll = $3
+            WHERE id = $1 AND campaign_id = $2
+        "#).await?;
+
+        client.execute(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &next_roll.map(|r| r.as_str())]).await?;
+
+        Ok(())
+    }
+
+    async fn get_next_roll(&self, scope: Scope) -> Result<Option<NextRoll>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT next_roll
+            FROM guilds
+            WHERE id = $1 AND campaign_id = $2
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        let next_roll: Option<String> = res.and_then(|row| row.get(0));
+
+        next_roll.map(|r| r.parse()).transpose()
+    }
+
+    async fn save_candidates(&self, scope: Scope, candidates: (String, String)) -> Result<(String, String)> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO guilds (id, campaign_id, candidates)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (id, campaign_id) DO UPDATE
+            SET candidates = COALESCE(guilds.candidates, EXCLUDED.candidates)
+            RETURNING candidates
+        "#).await?;
+
+        let row = client.query_one(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &vec![candidates.0, candidates.1]]).await?;
+
+        match row.get::<_, Vec<String>>(0).as_slice() {
+            [first, second] => Ok((first.clone(), second.clone())),
+            c => Err(anyhow!(format!("Wrong candidates {:?}", c))),
+        }
+    }
+
+    async fn reveal_signs(&self, scope: Scope, events: WebhookEvents<'_>) -> Result<Vec<SignInfo>> {
+        let mut client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+        let tx = client.transaction().await?;
+
+        let stmt = tx.prepare(&format!(r#"
+            UPDATE signs
+            SET hidden = false
+            WHERE guild_id = $1 AND campaign_id = $2 AND hidden AND NOT discarded AND session_id = {}
+            RETURNING id, sign_id, created_at, created_by_id, state, state_made_by_id, modifiable, hidden
+        "#, current_session_sql("$1", "$2"))).await?;
+
+        let mut signs = tx.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?
+            .into_iter()
+            .map(sign_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        signs.sort_by_key(|s| s.row_id);
+
+        enqueue_webhook_events(&tx, scope, events(&signs)?).await?;
+        tx.commit().await?;
+
+        Ok(signs)
+    }
+
+    async fn start_session(&self, scope: Scope, event_id: Option<u64>) -> Result<Session> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO sessions (guild_id, campaign_id, started_at, event_id)
+            VALUES ($1, $2, NOW(), $3)
+            RETURNING id, guild_id, started_at, event_id, campaign_id
+        "#).await?;
+
+        let row = client.query_one(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &event_id.map(|e| e.to_string())]).await?;
+
+        session_from_row(row)
+    }
+
+    async fn get_current_session(&self, scope: Scope) -> Result<Option<Session>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(&format!(r#"
+            SELECT id, guild_id, started_at, event_id, campaign_id
+            FROM sessions
+            WHERE id = {}
+        "#, current_session_sql("$1", "$2"))).await?;
+
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        res.map(session_from_row).transpose()
     }
+
+    async fn save_guild_settings(&self, settings: GuildSettings) -> Result<()> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO guild_settings (guild_id, modify_roll_mode, assist_max_helpers, assist_bonus, assist_timeout_secs, cadence,
+                auto_roll_channel_id, auto_roll_event_name, auto_roll_event_channel_id, campaign_id,
+                power_min, power_max, power_baseline, power_decay, ranks, rank_roles, modify_role_ids, roll_role_ids,
+                sign_pack, timezone, gm_role_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)
+            ON CONFLICT (guild_id, campaign_id) DO UPDATE
+            SET modify_roll_mode = $2, assist_max_helpers = $3, assist_bonus = $4, assist_timeout_secs = $5, cadence = $6,
+                auto_roll_channel_id = $7, auto_roll_event_name = $8, auto_roll_event_channel_id = $9,
+                power_min = $11, power_max = $12, power_baseline = $13, power_decay = $14, ranks = $15,
+                rank_roles = $16, modify_role_ids = $17, roll_role_ids = $18,
+                sign_pack = $19, timezone = $20, gm_role_id = $21
+        "#).await?;
+
+        // Setup choices are kept only in settings of guild
+        let guild_wide = settings.campaign_id == 0;
+
+        client.execute(&stmt, &[
+            &settings.guild_id.to_string(),
+            &settings.modify_roll_mode.as_str(),
+            &settings.assist_max_helpers,
+            &settings.assist_bonus,
+            &settings.assist_timeout_secs,
+            &settings.cadence.as_str(),
+            &settings.auto_roll_channel_id.map(|c| c.to_string()),
+            &settings.auto_roll_event_name,
+            &settings.auto_roll_event_channel_id.map(|c| c.to_string()),
+            &settings.campaign_id,
+            &settings.power_min,
+            &settings.power_max,
+            &settings.power_baseline,
+            &settings.power_decay,
+            &settings.ranks.to_string(),
+            &settings.rank_roles.to_string(),
+            &ids_to_str(&settings.modify_role_ids),
+            &ids_to_str(&settings.roll_role_ids),
+            &settings.sign_pack.as_ref().filter(|_| guild_wide),
+            &settings.timezone.as_ref().filter(|_| guild_wide),
+            &settings.gm_role_id.filter(|_| guild_wide).map(|r| r.to_string())
+        ]).await?;
+
+        Ok(())
+    }
+
+    async fn get_guild_settings(&self, scope: Scope) -> Result<Option<GuildSettings>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        // Own settings of campaign go first, settings of guild are used as fallback
+        // Setup choices always come from settings of guild
+        let stmt = client.prepare(r#"
+            SELECT s.modify_roll_mode, s.assist_max_helpers, s.assist_bonus, s.assist_timeout_secs, s.cadence,
+                s.auto_roll_channel_id, s.auto_roll_event_name, s.auto_roll_event_channel_id,
+                s.power_min, s.power_max, s.power_baseline, s.power_decay, s.ranks, s.rank_roles, s.modify_role_ids, s.roll_role_ids,
+                g.sign_pack, g.timezone, g.gm_role_id
+            FROM guild_settings s
+            LEFT JOIN guild_settings g ON g.guild_id = s.guild_id AND g.campaign_id = 0
+            WHERE s.guild_id = $1 AND s.campaign_id IN (0, $2)
+            ORDER BY s.campaign_id DESC
+            LIMIT 1
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        if res.is_none() {
+            return Ok(None);
+        }
+
+        let row = res.unwrap();
+
+        let modify_roll_mode: String = row.get(0);
+        let cadence: String = row.get(4);
+        let auto_roll_channel_id: Option<String> = row.get(5);
+        let auto_roll_event_channel_id: Option<String> = row.get(7);
+        let ranks: String = row.get(12);
+        let rank_roles: String = row.get(13);
+        let modify_role_ids: String = row.get(14);
+        let roll_role_ids: String = row.get(15);
+        let gm_role_id: Option<String> = row.get(18);
+
+        Ok(Some(GuildSettings {
+            guild_id: scope.guild_id,
+            campaign_id: scope.campaign_id,
+            modify_roll_mode: modify_roll_mode.parse()?,
+            assist_max_helpers: row.get(1),
+            assist_bonus: row.get(2),
+            assist_timeout_secs: row.get(3),
+            cadence: cadence.parse()?,
+            auto_roll_channel_id: auto_roll_channel_id.map(|c| c.parse()).transpose()?,
+            auto_roll_event_name: row.get(6),
+            auto_roll_event_channel_id: auto_roll_event_channel_id.map(|c| c.parse()).transpose()?,
+            power_min: row.get(8),
+            power_max: row.get(9),
+            power_baseline: row.get(10),
+            power_decay: row.get(11),
+            ranks: ranks.parse()?,
+            rank_roles: rank_roles.parse()?,
+            modify_role_ids: ids_from_str(&modify_role_ids)?,
+            roll_role_ids: ids_from_str(&roll_role_ids)?,
+            sign_pack: row.get(16),
+            timezone: row.get(17),
+            gm_role_id: gm_role_id.map(|r| r.parse()).transpose()?
+        }))
+    }
+
+    async fn is_valid_timezone(&self, timezone: String) -> Result<bool> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT EXISTS(SELECT 1 FROM pg_timezone_names WHERE name = $1)
+        "#).await?;
+
+        Ok(client.query_one(&stmt, &[&timezone]).await?.get(0))
+    }
+
+    async fn create_ritual(&self, scope: Scope, sign_row_id: i64, shaman_id: u64, use_luck: bool, channel_id: u64, timeout_secs: i32) -> Result<Option<Ritual>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO rituals (guild_id, shaman_id, use_luck, channel_id, expires_at, sign_row_id, campaign_id)
+            VALUES ($1, $2, $3, $4, NOW() + make_interval(secs => $5), $6, $7)
+            ON CONFLICT (guild_id, campaign_id) DO NOTHING
+            RETURNING guild_id, shaman_id, use_luck, channel_id, helpers, expires_at, sign_row_id, campaign_id, message_id
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[
+            &scope.guild_id.to_string(),
+            &shaman_id.to_string(),
+            &use_luck,
+            &channel_id.to_string(),
+            &(timeout_secs as f64),
+            &sign_row_id,
+            &scope.campaign_id
+        ]).await?;
+
+        res.map(ritual_from_row).transpose()
+    }
+
+    async fn get_ritual(&self, scope: Scope) -> Result<Option<Ritual>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT guild_id, shaman_id, use_luck, channel_id, helpers, expires_at, sign_row_id, campaign_id, message_id
+            FROM rituals
+            WHERE guild_id = $1 AND campaign_id = $2
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        res.map(ritual_from_row).transpose()
+    }
+
+    async fn set_ritual_message(&self, scope: Scope, message_id: u64) -> Result<()> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            UPDATE rituals
+            SET message_id = $3
+            WHERE guild_id = $1 AND campaign_id = $2
+        "#).await?;
+
+        client.execute(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &message_id.to_string()]).await?;
+
+        Ok(())
+    }
+
+    async fn add_ritual_helper(&self, scope: Scope, user_id: u64, max_helpers: i32) -> Result<Option<Ritual>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            UPDATE rituals
+            SET helpers = array_append(helpers, $2)
+            WHERE guild_id = $1 AND campaign_id = $4 AND shaman_id <> $2 AND NOT ($2 = ANY(helpers)) AND cardinality(helpers) < $3
+            RETURNING guild_id, shaman_id, use_luck, channel_id, helpers, expires_at, sign_row_id, campaign_id, message_id
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &user_id.to_string(), &max_helpers, &scope.campaign_id]).await?;
+
+        res.map(ritual_from_row).transpose()
+    }
+
+    async fn take_ritual(&self, scope: Scope) -> Result<Option<Ritual>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            DELETE FROM rituals
+            WHERE guild_id = $1 AND campaign_id = $2
+            RETURNING guild_id, shaman_id, use_luck, channel_id, helpers, expires_at, sign_row_id, campaign_id, message_id
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        res.map(ritual_from_row).transpose()
+    }
+
+    async fn take_expired_rituals(&self) -> Result<Vec<Ritual>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            DELETE FROM rituals
+            WHERE expires_at <= NOW()
+            RETURNING guild_id, shaman_id, use_luck, channel_id, helpers, expires_at, sign_row_id, campaign_id, message_id
+        "#).await?;
+
+        let res = client.query(&stmt, &[]).await?;
+
+        res.into_iter().map(ritual_from_row).collect()
+    }
+
+    async fn create_campaign(&self, guild_id: u64, name: String, pack: Option<String>) -> Result<Option<Campaign>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO campaigns (guild_id, name, pack)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (guild_id, name) DO NOTHING
+            RETURNING id
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&guild_id.to_string(), &name, &pack]).await?;
+
+        Ok(res.map(|row| Campaign {
+            id: row.get(0),
+            guild_id,
+            name,
+            pack,
+            channels: vec![]
+        }))
+    }
+
+    async fn get_campaigns(&self, guild_id: u64) -> Result<Vec<Campaign>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT c.id, c.name, c.pack, COALESCE(array_agg(cc.channel_id) FILTER (WHERE cc.channel_id IS NOT NULL), '{}')
+            FROM campaigns c
+            LEFT JOIN campaign_channels cc ON cc.campaign_id = c.id
+            WHERE c.guild_id = $1
+            GROUP BY c.id
+            ORDER BY c.id
+        "#).await?;
+
+        let res = client.query(&stmt, &[&guild_id.to_string()]).await?;
+
+        res.into_iter().map(|row| {
+            let channels: Vec<String> = row.get(3);
+
+            Ok(Campaign {
+                id: row.get(0),
+                guild_id,
+                name: row.get(1),
+                pack: row.get(2),
+                channels: channels.iter().map(|c| c.parse()).collect::<Result<_, _>>()?
+            })
+        }).collect()
+    }
+
+    async fn bind_channel(&self, guild_id: u64, campaign_name: String, channel_id: u64) -> Result<Option<Campaign>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO campaign_channels (channel_id, campaign_id)
+            SELECT $3, id
+            FROM campaigns
+            WHERE guild_id = $1 AND name = $2
+            ON CONFLICT (channel_id) DO UPDATE
+            SET campaign_id = EXCLUDED.campaign_id
+        "#).await?;
+
+        let updated = client.execute(&stmt, &[&guild_id.to_string(), &campaign_name, &channel_id.to_string()]).await?;
+
+        if updated == 0 {
+            return Ok(None);
+        }
+
+        Ok(self.get_campaigns(guild_id).await?
+            .into_iter()
+            .find(|c| c.name == campaign_name))
+    }
+
+    async fn unbind_channel(&self, guild_id: u64, channel_id: u64) -> Result<bool> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            DELETE FROM campaign_channels cc
+            USING campaigns c
+            WHERE cc.campaign_id = c.id AND c.guild_id = $1 AND cc.channel_id = $2
+        "#).await?;
+
+        Ok(client.execute(&stmt, &[&guild_id.to_string(), &channel_id.to_string()]).await? > 0)
+    }
+
+    async fn get_channel_scope(&self, guild_id: u64, channel_id: u64) -> Result<Scope> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT c.id
+            FROM campaign_channels cc
+            JOIN campaigns c ON c.id = cc.campaign_id
+            WHERE c.guild_id = $1 AND cc.channel_id = $2
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&guild_id.to_string(), &channel_id.to_string()]).await?;
+
+        Ok(match res {
+            Some(row) => Scope::campaign(guild_id, row.get(0)),
+            None => Scope::guild(guild_id),
+        })
+    }
+
+    async fn get_sign_pack(&self, scope: Scope) -> Result<Option<String>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT COALESCE(
+                (SELECT pack FROM campaigns WHERE guild_id = $1 AND id = $2),
+                (SELECT sign_pack FROM guild_settings WHERE guild_id = $1 AND campaign_id = 0)
+            )
+        "#).await?;
+
+        Ok(client.query_one(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?.get(0))
+    }
+
+    async fn create_webhook(&self, scope: Scope, url: String, secret: String) -> Result<Option<Webhook>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO webhooks (guild_id, campaign_id, url, secret)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (guild_id, campaign_id, url) DO NOTHING
+            RETURNING id
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &url, &secret]).await?;
+
+        Ok(res.map(|row| Webhook {
+            id: row.get(0),
+            guild_id: scope.guild_id,
+            campaign_id: scope.campaign_id,
+            url,
+            secret
+        }))
+    }
+
+    async fn get_webhooks(&self, scope: Scope) -> Result<Vec<Webhook>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT id, url, secret
+            FROM webhooks
+            WHERE guild_id = $1 AND campaign_id = $2
+            ORDER BY id
+        "#).await?;
+
+        let res = client.query(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        Ok(res.into_iter().map(|row| Webhook {
+            id: row.get(0),
+            guild_id: scope.guild_id,
+            campaign_id: scope.campaign_id,
+            url: row.get(1),
+            secret: row.get(2)
+        }).collect())
+    }
+
+    async fn delete_webhook(&self, scope: Scope, id: i64) -> Result<bool> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            DELETE FROM webhooks
+            WHERE id = $1 AND guild_id = $2 AND campaign_id = $3
+        "#).await?;
+
+        let res = client.execute(&stmt, &[&id, &scope.guild_id.to_string(), &scope.campaign_id]).await?;
+
+        Ok(res > 0)
+    }
+
+    async fn get_due_webhook_events(&self, limit: i64) -> Result<Vec<OutboxEvent>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT o.id, w.url, w.secret, o.event_type, o.payload, o.attempts
+            FROM webhook_outbox o
+            JOIN webhooks w ON w.id = o.webhook_id
+            WHERE o.delivered_at IS NULL AND o.next_attempt_at <= NOW()
+            ORDER BY o.id
+            LIMIT $1
+        "#).await?;
+
+        let res = client.query(&stmt, &[&limit]).await?;
+
+        Ok(res.into_iter().map(|row| OutboxEvent {
+            id: row.get(0),
+            url: row.get(1),
+            secret: row.get(2),
+            event_type: row.get(3),
+            payload: row.get(4),
+            attempts: row.get(5)
+        }).collect())
+    }
+
+    async fn mark_webhook_delivered(&self, event_id: i64) -> Result<()> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            UPDATE webhook_outbox
+            SET delivered_at = NOW(), next_attempt_at = NULL
+            WHERE id = $1
+        "#).await?;
+
+        client.execute(&stmt, &[&event_id]).await?;
+
+        Ok(())
+    }
+
+    async fn mark_webhook_failed(&self, event_id: i64, error: String, retry_in: Option<Duration>) -> Result<()> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        // Event without next attempt stays in outbox for investigation
+        let stmt = client.prepare(r#"
+            UPDATE webhook_outbox
+            SET attempts = attempts + 1,
+                last_error = $2,
+                next_attempt_at = NOW() + make_interval(secs => $3)
+            WHERE id = $1
+        "#).await?;
+
+        client.execute(&stmt, &[&event_id, &error, &retry_in.map(|d| d.as_secs_f64())]).await?;
+
+        Ok(())
+    }
+
+    async fn create_api_token(&self, guild_id: u64, name: String, token_hash: String, created_by: u64) -> Result<Option<ApiToken>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            INSERT INTO api_tokens (guild_id, name, token_hash, created_by_id, created_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            ON CONFLICT (guild_id, name) DO NOTHING
+            RETURNING id, created_at
+        "#).await?;
+
+        let res = client.query_opt(&stmt, &[&guild_id.to_string(), &name, &token_hash, &created_by.to_string()]).await?;
+
+        Ok(res.map(|row| ApiToken {
+            id: row.get(0),
+            guild_id,
+            name,
+            created_by_user_id: created_by,
+            created_at: row.get(1)
+        }))
+    }
+
+    async fn get_api_tokens(&self, guild_id: u64) -> Result<Vec<ApiToken>> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT id, name, created_by_id, created_at
+            FROM api_tokens
+            WHERE guild_id = $1
+            ORDER BY id
+        "#).await?;
+
+        let res = client.query(&stmt, &[&guild_id.to_string()]).await?;
+
+        res.into_iter().map(|row| {
+            let created_by: String = row.get(2);
+
+            Ok(ApiToken {
+                id: row.get(0),
+                guild_id,
+                name: row.get(1),
+                created_by_user_id: created_by.parse()?,
+                created_at: row.get(3)
+            })
+        }).collect()
+    }
+
+    async fn delete_api_token(&self, guild_id: u64, name: String) -> Result<bool> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            DELETE FROM api_tokens
+            WHERE guild_id = $1 AND name = $2
+        "#).await?;
+
+        Ok(client.execute(&stmt, &[&guild_id.to_string(), &name]).await? > 0)
+    }
+
+    async fn check_api_token(&self, guild_id: u64, token_hash: String) -> Result<bool> {
+        let client = self.pool.get().await
+            .with_context(|| "Cannot get connection")?;
+
+        let stmt = client.prepare(r#"
+            SELECT 1
+            FROM api_tokens
+            WHERE guild_id = $1 AND token_hash = $2
+        "#).await?;
+
+        Ok(client.query_opt(&stmt, &[&guild_id.to_string(), &token_hash]).await?.is_some())
+    }
+}
+
+/**
+ * Subquery for id of current session of scope passed in `guild_param` and `campaign_param`
+ * Daily cadence uses session started today in timezone of guild, other cadences use the latest started session
+ * Campaign without own settings uses cadence of guild
+ */
+fn current_session_sql(guild_param: &str, campaign_param: &str) -> String {
+    format!(r#"(
+        SELECT s.id
+        FROM sessions s
+        LEFT JOIN LATERAL (
+            SELECT gs.cadence
+            FROM guild_settings gs
+            WHERE gs.guild_id = s.guild_id AND gs.campaign_id IN (0, s.campaign_id)
+            ORDER BY gs.campaign_id DESC
+            LIMIT 1
+        ) gs ON true
+        LEFT JOIN guild_settings g ON g.guild_id = s.guild_id AND g.campaign_id = 0
+        WHERE s.guild_id = {0} AND s.campaign_id = {1} AND (
+            COALESCE(gs.cadence, 'daily') <> 'daily'
+            OR s.started_at >= COALESCE(
+                (date_trunc('day', NOW() AT TIME ZONE g.timezone) AT TIME ZONE g.timezone)::timestamp,
+                NOW()::date
+            )
+        )
+        ORDER BY s.id DESC
+        LIMIT 1
+    )"#, guild_param, campaign_param)
+}
+
+/**
+ * Maps row of `id, guild_id, started_at, event_id, campaign_id` from sessions table
+ */
+fn session_from_row(row: tokio_postgres::Row) -> Result<Session> {
+    let guild_id: String = row.get(1);
+    let event_id: Option<String> = row.get(3);
+
+    Ok(Session {
+        id: row.get(0),
+        guild_id: guild_id.parse()?,
+        campaign_id: row.get(4),
+        started_at: row.get(2),
+        event_id: event_id.map(|e| e.parse()).transpose()?
+    })
+}
+
+fn parse_sign_state(state: String, made_by_id: Option<String>) -> Result<SignState> {
+    let by_user_id = || -> Result<u64> {
+        Ok(made_by_id.clone().ok_or(anyhow!("State changer not set"))?.parse()?)
+    };
+
+    Ok(match state.as_str() {
+        "Created" => SignState::Created,
+        "Success" => SignState::Success { by_user_id: by_user_id()? },
+        "Failed" => SignState::Failed { by_user_id: by_user_id()? },
+        "CriticalSuccess" => SignState::CriticalSuccess { by_user_id: by_user_id()? },
+        "CriticalFailure" => SignState::CriticalFailure { by_user_id: by_user_id()? },
+        s => return Err(anyhow!(format!("Unknown sign state {}", s))),
+    })
+}
+
+/**
+ * Maps row of `id, sign_id, created_at, created_by_id, state, state_made_by_id, modifiable, hidden` from signs table
+ */
+fn sign_from_row(row: tokio_postgres::Row) -> Result<SignInfo> {
+    let created_by_id: String = row.get(3);
+
+    Ok(SignInfo {
+        row_id: row.get(0),
+        id: row.get(1),
+        created_by_user_id: created_by_id.parse()?,
+        state: parse_sign_state(row.get(4), row.get(5))?,
+        created_at: row.get(2),
+        modifiable: row.get(6),
+        hidden: row.get(7)
+    })
+}
+
+/**
+ * Discord ids separated by commas
+ */
+fn ids_to_str(ids: &[u64]) -> String {
+    ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn ids_from_str(ids: &str) -> Result<Vec<u64>> {
+    ids.split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| Ok(id.parse()?))
+        .collect()
+}
+
+/**
+ * Maps row of `id, guild_id, shaman_power, luck_dice, character id, character name, power_updated_at`
+ * from users joined with active character
+ */
+fn user_from_row(row: tokio_postgres::Row, scope: Scope) -> Result<UserInfo> {
+    let id: String = row.get(0);
+
+    Ok(UserInfo {
+        id: id.parse()?,
+        guild_id: scope.guild_id,
+        campaign_id: scope.campaign_id,
+        shaman_power: row.get(2),
+        luck_dice: row.get(3),
+        active_character_id: row.get(4),
+        active_character_name: row.get(5),
+        power_updated_at: row.get(6)
+    })
+}
+
+/**
+ * Maps row of `id, owner_id, name, shaman_power` from characters table
+ */
+fn character_from_row(row: tokio_postgres::Row) -> Result<Character> {
+    let owner_id: String = row.get(1);
+
+    Ok(Character {
+        id: row.get(0),
+        owner_id: owner_id.parse()?,
+        name: row.get(2),
+        shaman_power: row.get(3)
+    })
+}
+
+/**
+ * Puts events to outbox of every webhook of scope within transaction of sign change
+ */
+async fn enqueue_webhook_events(tx: &Transaction<'_>, scope: Scope, events: Vec<WebhookEvent>) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let stmt = tx.prepare(r#"
+        INSERT INTO webhook_outbox (webhook_id, event_type, payload, created_at, next_attempt_at)
+        SELECT id, $3, $4, NOW(), NOW()
+        FROM webhooks
+        WHERE guild_id = $1 AND campaign_id = $2
+    "#).await?;
+
+    for event in events {
+        tx.execute(&stmt, &[&scope.guild_id.to_string(), &scope.campaign_id, &event.event_type, &event.payload]).await?;
+    }
+
+    Ok(())
+}
+
+fn ritual_from_row(row: tokio_postgres::Row) -> Result<Ritual> {
+    let guild_id: String = row.get(0);
+    let shaman_id: String = row.get(1);
+    let channel_id: String = row.get(3);
+    let helpers: Vec<String> = row.get(4);
+    let message_id: Option<String> = row.get(8);
+
+    Ok(Ritual {
+        guild_id: guild_id.parse()?,
+        campaign_id: row.get(7),
+        shaman_id: shaman_id.parse()?,
+        use_luck: row.get(2),
+        channel_id: channel_id.parse()?,
+        message_id: message_id.map(|m| m.parse()).transpose()?,
+        helpers: helpers.iter().map(|h| h.parse()).collect::<Result<_, _>>()?,
+        expires_at: row.get(5),
+        sign_row_id: row.get(6)
+    })
 }
 
 pub async fn init_with_config(cfg: deadpool_postgres::Config) -> Result<PsqlDao> {
@@ -0,0 +1,95 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::anyhow;
+use rand::Rng;
+use serde::Deserialize;
+
+/**
+ * How d20 is rolled: once, or twice keeping the highest or the lowest result
+ */
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollMode {
+    #[default]
+    Normal,
+    Advantage,
+    Disadvantage
+}
+
+impl RollMode {
+    /**
+     * Combines roll modes from different sources
+     * Advantage and disadvantage cancel each other out
+     */
+    pub fn combine(modes: &[RollMode]) -> RollMode {
+        let advantage = modes.contains(&RollMode::Advantage);
+        let disadvantage = modes.contains(&RollMode::Disadvantage);
+
+        match (advantage, disadvantage) {
+            (true, false) => RollMode::Advantage,
+            (false, true) => RollMode::Disadvantage,
+            _ => RollMode::Normal,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RollMode::Normal => "normal",
+            RollMode::Advantage => "advantage",
+            RollMode::Disadvantage => "disadvantage",
+        }
+    }
+}
+
+impl FromStr for RollMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(RollMode::Normal),
+            "advantage" => Ok(RollMode::Advantage),
+            "disadvantage" => Ok(RollMode::Disadvantage),
+            s => Err(anyhow!(format!("Unknown roll mode {}", s))),
+        }
+    }
+}
+
+impl Display for RollMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RollMode::Normal => write!(f, "d20"),
+            RollMode::Advantage => write!(f, "2d20 с преимуществом"),
+            RollMode::Disadvantage => write!(f, "2d20 с помехой"),
+        }
+    }
+}
+
+/**
+ * Result of d20 roll with all thrown dice
+ */
+#[derive(Debug, Clone)]
+pub struct D20Roll {
+    pub mode: RollMode,
+    pub dice: Vec<i32>,
+    pub result: i32
+}
+
+impl D20Roll {
+    pub fn roll(mode: RollMode) -> Self {
+        let count = if mode == RollMode::Normal {1} else {2};
+        let dice: Vec<i32> = (0..count)
+            .map(|_| rand::thread_rng().gen_range(1..=20))
+            .collect();
+
+        let result = match mode {
+            RollMode::Disadvantage => *dice.iter().min().unwrap(),
+            _ => *dice.iter().max().unwrap(),
+        };
+
+        D20Roll { mode, dice, result }
+    }
+
+    pub fn dice_str(&self) -> String {
+        self.dice.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")
+    }
+}
@@ -1,16 +1,72 @@
+use std::{collections::HashSet, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::Duration};
+
 use anyhow::{anyhow, Context, Result};
 use log::{debug, error, info};
-use serenity::{all::{CacheHttp, ComponentInteractionData, ComponentInteractionDataKind, CreateInteractionResponse, EventHandler, GuildId, Http, Interaction, Ready}, async_trait};
+use serenity::{all::{CacheHttp, ComponentInteractionDataKind, CreateInteractionResponse, EventHandler, Guild, GuildId, Http, Interaction, Ready, ScheduledEvent, ScheduledEventStatus}, async_trait};
 
-use crate::{commands::{self, utils}, db::Dao};
+use crate::{commands::{self, utils}, config::InteractionSource, db::{Cadence, Dao, GuildSettings, Scope}, webhooks};
 
+/**
+ * Shared by gateway client and HTTP endpoint when both are running
+ */
 pub struct Handler {
-    dao: Box<dyn Dao>
+    dao: Arc<dyn Dao>,
+    interaction_source: InteractionSource,
+    background_started: AtomicBool,
+    /// Guilds bot was in when connected to gateway, others are joined later
+    known_guilds: Mutex<HashSet<u64>>
 }
 
 impl Handler {
-    pub fn new(dao: Box<dyn Dao>) -> Self {
-        Handler { dao }
+    pub fn new(dao: Arc<dyn Dao>, interaction_source: InteractionSource) -> Self {
+        Handler {
+            dao,
+            interaction_source,
+            background_started: AtomicBool::new(false),
+            known_guilds: Mutex::new(HashSet::new())
+        }
+    }
+
+    /**
+     * Spawns periodic tasks which are not triggered by interactions
+     * Safe to call multiple times (gateway calls `ready` on every reconnect)
+     */
+    pub fn start_background_tasks(&self, http: Arc<Http>) {
+        if self.background_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let dao = self.dao.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = commands::ritual::resolve_expired(dao.as_ref(), &http).await {
+                    error!("Cannot resolve expired rituals: {}", e);
+                }
+            }
+        });
+
+        let dao = self.dao.clone();
+
+        tokio::spawn(async move {
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("Cannot create webhook client");
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = webhooks::deliver_due(dao.as_ref(), &client).await {
+                    error!("Cannot deliver webhook events: {}", e);
+                }
+            }
+        });
     }
 
     pub async fn init_guilds(&self, ctx: &(impl AsRef<Http> + CacheHttp)) -> Result<()> {
@@ -31,7 +87,21 @@ impl Handler {
         GuildId::new(guild_id).set_commands(ctx, vec![
             commands::sign_roll::register(),
             commands::sign_current::register(),
-            commands::sign_my_power::register()
+            commands::sign_my_power::register(),
+            commands::sign_pack::register(),
+            commands::sign_luck::register(),
+            commands::sign_settings::register(),
+            commands::sign_session::register(),
+            commands::sign_reveal::register(),
+            commands::sign_campaign::register(),
+            commands::sign_character::register(),
+            commands::sign_roles::register(),
+            commands::sign_stats::register(),
+            commands::sign_guild_stats::register(),
+            commands::sign_achievements::register(),
+            commands::sign_webhook::register(),
+            commands::sign_api_token::register(),
+            commands::sign_setup::register()
         ])
         .await.with_context(|| format!("Cannot init commands in guild {}", guild_id))?;
 
@@ -49,18 +119,45 @@ impl Handler {
                     "sign_roll" => commands::sign_roll::run(&self, &ctx, &command).await,
                     "sign_current" => commands::sign_current::run(&self, &ctx, &command).await,
                     "sign_my_power" => commands::sign_my_power::run(&self, &ctx, &command).await,
+                    "sign_pack" => commands::sign_pack::run(self, &ctx, command).await,
+                    "sign_luck" => commands::sign_luck::run(self, &ctx, command).await,
+                    "sign_settings" => commands::sign_settings::run(self, &ctx, command).await,
+                    "sign_session" => commands::sign_session::run(self, &ctx, command).await,
+                    "sign_reveal" => commands::sign_reveal::run(self, &ctx, command).await,
+                    "sign_campaign" => commands::sign_campaign::run(self, &ctx, command).await,
+                    "sign_character" => commands::sign_character::run(self, &ctx, command).await,
+                    "sign_roles" => commands::sign_roles::run(self, &ctx, command).await,
+                    "sign_stats" => commands::sign_stats::run(self, &ctx, command).await,
+                    "sign_guild_stats" => commands::sign_guild_stats::run(self, &ctx, command).await,
+                    "sign_achievements" => commands::sign_achievements::run(self, &ctx, command).await,
+                    "sign_webhook" => commands::sign_webhook::run(self, &ctx, command).await,
+                    "sign_api_token" => commands::sign_api_token::run(self, &ctx, command).await,
+                    "sign_setup" => commands::sign_setup::run(self, &ctx, command).await,
                     cmd => Err(anyhow!(format!("Command {} not found", cmd))),
                 }
             },
             Interaction::Component(component) => {
-                match &component.data {
-                    ComponentInteractionData {custom_id, kind: ComponentInteractionDataKind::Button, ..} => {
-                        match custom_id.as_str() {
-                            "change_sign" => commands::modify_sign::run(&self, &ctx, component).await,
+                let custom_id = component.data.custom_id.clone();
+                let (name, args) = utils::parse_custom_id(&custom_id);
+
+                match component.data.kind.clone() {
+                    ComponentInteractionDataKind::Button => {
+                        match name {
+                            "change_sign" => commands::modify_sign::run(self, &ctx, component, &args).await,
+                            "sign_pack" => commands::sign_pack::run_component(self, &ctx, component, &args, &[]).await,
+                            "ritual" => commands::ritual::run_component(self, &ctx, component, &args).await,
+                            "sign_reveal" => commands::sign_reveal::run_component(self, &ctx, component).await,
                             cmd => Err(anyhow!(format!("Component not found {}", cmd)))
                         }
                     }
-                    s => Err(anyhow!(format!("Component not found {:?}", s.kind)))
+                    ComponentInteractionDataKind::StringSelect { values } => {
+                        match name {
+                            "sign_pack" => commands::sign_pack::run_component(self, &ctx, component, &args, &values).await,
+                            "sign_choose" => commands::sign_roll::run_choose(self, &ctx, component, &args, &values).await,
+                            cmd => Err(anyhow!(format!("Component not found {}", cmd)))
+                        }
+                    }
+                    s => Err(anyhow!(format!("Component not found {:?}", s)))
                 }
             },
             Interaction::Ping(_) => Ok(CreateInteractionResponse::Pong),
@@ -76,7 +173,46 @@ impl Handler {
         res.unwrap()
     }
 
-    pub fn dao(&self) -> &Box<dyn Dao> {
+    /**
+     * Handles start of scheduled event in guild and all its campaigns whose event filters match
+     * Starts new session in scope with `scheduled_events` cadence and rolls sign if auto roll channel is set
+     */
+    pub async fn on_event_start(&self, http: &Http, event: &ScheduledEvent) -> Result<()> {
+        let guild_id = event.guild_id.get();
+        let event_id = event.id.get();
+
+        let mut scopes = vec![Scope::guild(guild_id)];
+        scopes.extend(self.dao.get_campaigns(guild_id).await?.iter().map(|c| c.scope()));
+
+        for scope in scopes {
+            let settings = self.dao.get_guild_settings(scope).await?
+                .unwrap_or(GuildSettings::new(scope));
+
+            if !settings.event_matches(&event.name, event.channel_id.map(|c| c.get())) {
+                continue;
+            }
+
+            if settings.cadence == Cadence::ScheduledEvents {
+                let current = self.dao.get_current_session(scope).await?;
+
+                if current.is_none_or(|s| s.event_id != Some(event_id)) {
+                    info!("Event {} started in guild {}, starting new session in campaign {}", event_id, guild_id, scope.campaign_id);
+                    self.dao.start_session(scope, Some(event_id)).await?;
+                }
+            }
+
+            if settings.auto_roll_channel_id.is_none() {
+                continue;
+            }
+
+            let bot_id = http.get_current_user().await?.id.get();
+            commands::sign_roll::auto_roll(self.dao.as_ref(), http, &settings, bot_id).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn dao(&self) -> &Arc<dyn Dao> {
         &self.dao
     }
 }
@@ -84,14 +220,44 @@ impl Handler {
 #[async_trait]
 impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: serenity::all::Context, interaction: Interaction) {
+        // Interactions are answered by HTTP endpoint, gateway only delivers events then
+        if self.interaction_source != InteractionSource::Gateway {
+            return;
+        }
+
         let res = self.handle_interaction(&ctx, interaction.clone()).await;
         let _ = send_resp(interaction, res, &ctx).await;
     }
 
     async fn ready(&self, ctx: serenity::all::Context, ready: Ready) {
         info!("{} is connected!", ready.user.name);
+        self.known_guilds.lock().unwrap().extend(ready.guilds.iter().map(|g| g.id.get()));
+        self.start_background_tasks(ctx.http.clone());
         self.init_guilds(&ctx.clone()).await.expect("Cannot init commands for guilds");
     }
+
+    async fn guild_create(&self, ctx: serenity::all::Context, guild: Guild, _is_new: Option<bool>) {
+        // Guilds from ready are created on every connect and already have commands
+        if !self.known_guilds.lock().unwrap().insert(guild.id.get()) {
+            return;
+        }
+
+        info!("Bot joined guild {}", guild.id);
+
+        if let Err(e) = self.init_guild(&ctx, guild.id.get()).await {
+            error!("Cannot init commands for guild {}: {}", guild.id, e);
+        }
+    }
+
+    async fn guild_scheduled_event_update(&self, ctx: serenity::all::Context, event: ScheduledEvent) {
+        if event.status != ScheduledEventStatus::Active {
+            return;
+        }
+
+        if let Err(e) = self.on_event_start(&ctx.http, &event).await {
+            error!("Cannot process start of event {} in guild {}: {}", event.id, event.guild_id, e);
+        }
+    }
 }
 
 async fn send_resp(interaction: Interaction, resp: CreateInteractionResponse, ctx: &impl CacheHttp) -> Result<()> {
@@ -1,24 +1,29 @@
-use std::{convert::Infallible, env, net::{Ipv4Addr, SocketAddr, SocketAddrV4}, sync::Arc};
+use std::{convert::Infallible, env, net::{Ipv4Addr, SocketAddr, SocketAddrV4}, sync::Arc, time::{Duration, SystemTime}};
 use anyhow::{anyhow, Result};
 use hyper_util::rt::TokioIo;
-use log::error;
-use serenity::{all::Interaction, interactions_endpoint::Verifier, json, Client};
+use log::{error, info, warn};
+use serenity::{all::{Http, Interaction}, interactions_endpoint::Verifier, json};
 use http_body_util::{BodyExt, Full};
-use hyper::{body::{Bytes, Incoming}, server::conn::http1, service::service_fn, Request, Response};
+use hyper::{body::{Bytes, Incoming}, server::conn::http1, service::service_fn, Request, Response, StatusCode};
 use tokio::net::TcpListener;
 use querystring::querify;
 use std::collections::HashMap;
 
-use crate::{config::ServerConf, discord::Handler};
+use crate::{api::{self, Body}, config::{InteractionSource, ServerConf}, discord::Handler, oauth::{self, OAuthConf}, pages, setup_page};
 
 struct Server {
-    handler: Handler,
+    handler: Arc<Handler>,
+    /// Interactions endpoint is served only when Discord sends interactions here
+    interaction_source: InteractionSource,
     verifier: Verifier,
-    client: Client
+    http: Arc<Http>,
+    /// Bot installation is disabled without it
+    oauth: Option<OAuthConf>,
+    http_client: reqwest::Client
 }
 
 impl Server {
-    async fn handle(&self, req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    async fn handle(&self, req: Request<Incoming>) -> Result<Response<Body>, Infallible> {
         let resp = self.handle_impl(req).await;
 
         if let Err(e) = resp {
@@ -26,7 +31,7 @@ impl Server {
 
             let resp = Response::builder()
                 .status(500)
-                .body(Full::new(Bytes::new())).unwrap();
+                .body(Full::new(Bytes::new()).boxed_unsync()).unwrap();
 
             return Ok(resp);
         }
@@ -34,11 +39,36 @@ impl Server {
         Ok(resp.unwrap())
     }
 
-    async fn handle_impl(&self, req: Request<Incoming>) -> Result<Response<Full<Bytes>>> {
+    async fn handle_impl(&self, req: Request<Incoming>) -> Result<Response<Body>> {
+        if req.uri().path() == "/install" {
+            return self.install();
+        }
+
         if req.uri().path() == "/oauth" {
             return self.oauth(req).await;
         }
 
+        if req.uri().path().starts_with("/api/") {
+            return api::handle(self.handler.dao().as_ref(), &req).await;
+        }
+
+        if req.uri().path().starts_with("/pages/") {
+            return pages::handle(self.handler.dao().as_ref(), &req).await;
+        }
+
+        if req.uri().path().starts_with("/setup/") {
+            return setup_page::handle(self.handler.dao().as_ref(), &self.http, req).await;
+        }
+
+        // Gateway already handles interactions, so they are not handled twice
+        if self.interaction_source != InteractionSource::Http {
+            let resp = Response::builder()
+                .status(404)
+                .body(Full::new(Bytes::new()).boxed_unsync())?;
+
+            return Ok(resp);
+        }
+
         let headers = req.headers().clone();
 
         let find_header = |name| Some(headers.iter().find(|h| h.0 == name)?.1.to_str());
@@ -50,47 +80,139 @@ impl Server {
         if self.verifier.verify(signature, timestamp, &body).is_err() {
             let resp = Response::builder()
                 .status(403)
-                .body(Full::new(Bytes::new()))?;
+                .body(Full::new(Bytes::new()).boxed_unsync())?;
 
             return Ok(resp);
         }
 
         let interaction = json::from_slice::<Interaction>(&body)?;
 
-        let res = self.handler.handle_interaction(&self.client.http, interaction).await;
+        let res = self.handler.handle_interaction(&self.http, interaction).await;
         Ok(Response::builder()
             .header("Content-Type", "application/json")
             .status(200)
-            .body( Full::new(json::to_vec(&res)?.into()))?)
+            .body(Full::new(json::to_vec(&res)?.into()).boxed_unsync())?)
     }
 
-    async fn oauth(&self, req: Request<Incoming>) -> Result<Response<Full<Bytes>>> {
-        // TODO: make proper oauth process here
-        // I can add here bot setting process
-        // For now it is just OK page and handle to register bot commands on server
+    /**
+     * Starts bot installation: binds signed state to browser and sends GM to Discord
+     */
+    fn install(&self) -> Result<Response<Body>> {
+        let Some(conf) = &self.oauth else {
+            return pages::page(StatusCode::NOT_FOUND, "Не найдено", "<p>Установка бота не настроена.</p>");
+        };
 
-        let uri = req.uri();
-        let query: HashMap<&str, &str> = querify(uri.query().unwrap_or("")).into_iter().collect();
+        let nonce = oauth::new_nonce();
+        let state = oauth::make_state(&conf.secret, &nonce, SystemTime::now());
 
-        let guild = query.get("guild_id");
+        Ok(Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header("Location", oauth::authorize_url(conf, &state)?)
+            .header("Set-Cookie", format!(
+                "{}={}; Path=/oauth; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+                oauth::STATE_COOKIE, nonce, oauth::STATE_TTL.as_secs()
+            ))
+            .body(Full::new(Bytes::new()).boxed_unsync())?)
+    }
+
+    /**
+     * Redirect from Discord after installation, registers commands in guild from token response
+     */
+    async fn oauth(&self, req: Request<Incoming>) -> Result<Response<Body>> {
+        let Some(conf) = &self.oauth else {
+            return pages::page(StatusCode::NOT_FOUND, "Не найдено", "<p>Установка бота не настроена.</p>");
+        };
 
-        if let Some(guild_id) = guild {
-            let id: u64 = guild_id.parse()?;
-            self.handler.init_guild(&self.client.http, id).await?;
+        let query: HashMap<&str, &str> = querify(req.uri().query().unwrap_or("")).into_iter().collect();
+
+        if let Some(error) = query.get("error") {
+            info!("Bot installation is cancelled: {}", error);
+            return result_page(StatusCode::BAD_REQUEST, "Бот не добавлен: установка отменена.");
         }
 
-        Ok(Response::new(Full::new("Bot is added to server!".as_bytes().into())))
+        let nonce = req.headers().get_all("Cookie").iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(';'))
+            .find_map(|c| c.trim().strip_prefix(oauth::STATE_COOKIE)?.strip_prefix('='));
+
+        let state_valid = match (query.get("state"), nonce) {
+            (Some(state), Some(nonce)) => oauth::verify_state(&conf.secret, state, nonce, SystemTime::now()),
+            _ => false,
+        };
+
+        if !state_valid {
+            warn!("Bot installation with invalid state");
+            return result_page(StatusCode::BAD_REQUEST, "Ссылка устарела или открыта не в том браузере. Начните установку заново.");
+        }
+
+        let Some(code) = query.get("code") else {
+            return result_page(StatusCode::BAD_REQUEST, "Discord не передал код авторизации.");
+        };
+
+        let guild_id = match oauth::exchange_code(&self.http_client, conf, code).await {
+            Ok(guild_id) => guild_id,
+            Err(e) => {
+                warn!("Cannot exchange OAuth code: {}", e);
+                return result_page(StatusCode::BAD_GATEWAY, "Не удалось подтвердить установку в Discord. Попробуйте еще раз.");
+            },
+        };
+
+        self.handler.init_guild(&self.http, guild_id).await?;
+        info!("Bot is installed to guild {}", guild_id);
+
+        // Freshly installed guild is not configured, so GM goes to onboarding
+        let Some(setup_link) = setup_page::link(guild_id, SystemTime::now()) else {
+            return result_page(StatusCode::OK, "Бот добавлен на сервер! Настройте его командой /sign_setup.");
+        };
+
+        Ok(Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header("Location", format!("{}&installed=1", setup_link))
+            .body(Full::new(Bytes::new()).boxed_unsync())?)
     }
 }
 
-pub async fn start(handler: Handler, client: Client, config: ServerConf) -> Result<()> {
+fn result_page(status: StatusCode, message: &str) -> Result<Response<Body>> {
+    pages::page(status, "Установка бота", &format!("<h1>Установка бота</h1><p>{}</p>", pages::escape(message)))
+}
+
+fn oauth_conf(config: &ServerConf, client_id: u64) -> Option<OAuthConf> {
+    let public_url = config.public_url()?;
+
+    Some(OAuthConf {
+        api_url: config.discord_api_url().unwrap_or(oauth::DEFAULT_API_URL.to_string()),
+        client_id,
+        client_secret: config.client_secret()?,
+        redirect_url: format!("{}/oauth", public_url.trim_end_matches('/')),
+        secret: config.secret()?
+    })
+}
+
+/**
+ * Serves interactions endpoint and web pages, handler can be shared with gateway client
+ */
+pub async fn start(handler: Arc<Handler>, http: Arc<Http>, config: ServerConf, interaction_source: InteractionSource) -> Result<()> {
     let addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), env::var("PORT")?.parse()?));
     let listener = TcpListener::bind(addr).await?;
 
+    handler.start_background_tasks(http.clone());
+
+    let client_id = http.application_id().ok_or(anyhow!("Application id is not set"))?.get();
+    let oauth = oauth_conf(&config, client_id);
+
+    if oauth.is_none() {
+        warn!("Public url, secret or client secret is not set, bot installation is disabled");
+    }
+
     let server = Arc::new(Server {
-        handler: handler,
+        handler,
+        interaction_source,
         verifier: Verifier::new(&config.discord_pk()),
-        client: client
+        http,
+        oauth,
+        http_client: reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?
     });
 
     loop {
@@ -0,0 +1,53 @@
+use std::sync::OnceLock;
+
+use log::debug;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{api::SignView, db::{Scope, SignInfo}, signs::Pack};
+
+/// Slow subscribers skip events older than this many
+const CAPACITY: usize = 256;
+
+static BUS: OnceLock<broadcast::Sender<SignEvent>> = OnceLock::new();
+
+/**
+ * Change of guild sign, sent to live subscribers like stream overlays
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct SignEvent {
+    #[serde(skip)]
+    pub scope: Scope,
+    pub event: &'static str,
+    pub guild_id: String,
+    pub campaign_id: i64,
+    pub sign: SignView
+}
+
+fn bus() -> &'static broadcast::Sender<SignEvent> {
+    BUS.get_or_init(|| broadcast::channel(CAPACITY).0)
+}
+
+/**
+ * Sends events about signs to everyone subscribed now, hidden signs are skipped
+ */
+pub fn publish(scope: Scope, pack: &Pack, event: &'static str, signs: &[SignInfo]) {
+    for sign in signs.iter().filter(|s| !s.hidden) {
+        let sign_event = SignEvent {
+            scope,
+            event,
+            guild_id: scope.guild_id.to_string(),
+            campaign_id: scope.campaign_id,
+            sign: SignView::new(pack, sign)
+        };
+
+        // Error only means there are no subscribers
+        if let Ok(count) = bus().send(sign_event) {
+            debug!("Event {} of guild {} sent to {} subscribers", event, scope.guild_id, count);
+        }
+    }
+}
+
+pub fn subscribe() -> broadcast::Receiver<SignEvent> {
+    bus().subscribe()
+}
@@ -1,10 +1,23 @@
+use std::sync::Arc;
+
 use discord::Handler;
 use serenity::{all::ApplicationId, prelude::*};
 use dotenv::dotenv;
+use log::info;
 
+mod achievements;
+mod api;
 mod commands;
 mod db;
+mod dice;
 mod discord;
+mod events;
+mod oauth;
+mod pages;
+mod power;
+mod roles;
+mod setup_page;
+mod webhooks;
 pub mod signs;
 pub mod config;
 pub mod discord_endpoint_server;
@@ -18,30 +31,46 @@ async fn main() {
     env_logger::init();
     let config = config::AppConfig::from_env().unwrap();
     
-    signs::load_signs(config.sign_pack_path()).unwrap();
+    signs::load_signs(config.sign_pack_paths()).unwrap();
+    if let Some(path) = config.achievements_path() {
+        achievements::load_achievements(path).unwrap();
+    }
 
     let dao = db::psql::init_with_config(config.pg().clone()).await.unwrap();
-    let handler = Handler::new(Box::new(dao.clone()));
+    let handler = Arc::new(Handler::new(Arc::new(dao.clone()), config.interaction_source()));
 
     let token = config.discord_token();
-    let intents = GatewayIntents::GUILDS | GatewayIntents::GUILD_MEMBERS | GatewayIntents::GUILD_MESSAGES;
-
-    let client_builder = Client::builder(token, intents)
-        .application_id(ApplicationId::new(config.application_id()));
-
-    if let Some(cfg) = config.server() {
-        let client = client_builder.await.expect("Error creating client");
-        discord_endpoint_server::start(Handler::new(Box::new(dao.clone())), client, cfg.clone()).await.unwrap();
-        return;
-    }
+    let intents = GatewayIntents::GUILDS | GatewayIntents::GUILD_MEMBERS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::GUILD_SCHEDULED_EVENTS;
 
-    let mut client = client_builder
-        .event_handler(handler)
+    let mut client = Client::builder(token, intents)
+        .application_id(ApplicationId::new(config.application_id()))
+        .event_handler_arc(handler.clone())
         .await
         .expect("Error creating client");
-    
-    if let Err(why) = client.start().await {
-        println!("Client error: {why:?}");
+
+    let server = config.server().clone().map(|cfg| {
+        if let Some(public_url) = cfg.public_url() {
+            let secret = cfg.secret().expect("Server secret is required to serve pages");
+            pages::init(public_url, secret).unwrap();
+        }
+
+        discord_endpoint_server::start(handler.clone(), client.http.clone(), cfg, config.interaction_source())
+    });
+
+    info!("Interactions come from {:?}", config.interaction_source());
+
+    match (server, config.gateway_enabled()) {
+        // Gateway and endpoint share handler, bot stops when any of them stops
+        (Some(server), true) => tokio::select! {
+            res = server => res.unwrap(),
+            res = client.start() => if let Err(why) = res {
+                println!("Client error: {why:?}");
+            },
+        },
+        (Some(server), false) => server.await.unwrap(),
+        (None, _) => if let Err(why) = client.start().await {
+            println!("Client error: {why:?}");
+        },
     }
 }
 
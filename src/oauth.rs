@@ -0,0 +1,115 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use serenity::all::Permissions;
+
+use crate::{commands::utils, webhooks};
+
+pub const DEFAULT_API_URL: &str = "https://discord.com/api/v10";
+pub const STATE_COOKIE: &str = "oauth_state";
+/// Time to finish installation in Discord
+pub const STATE_TTL: Duration = Duration::from_secs(600);
+/// Bot posts signs to channels and syncs rank roles
+const BOT_PERMISSIONS: Permissions = Permissions::VIEW_CHANNEL
+    .union(Permissions::SEND_MESSAGES)
+    .union(Permissions::EMBED_LINKS)
+    .union(Permissions::MANAGE_ROLES);
+
+/**
+ * Settings of authorization code flow used to add bot to guild
+ */
+#[derive(Debug, Clone)]
+pub struct OAuthConf {
+    /// Discord API, replaced by local stub in tests
+    pub api_url: String,
+    pub client_id: u64,
+    pub client_secret: String,
+    /// Address of `/oauth` endpoint, should be registered in Discord application
+    pub redirect_url: String,
+    /// Key to sign `state` parameter
+    pub secret: String
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    guild: Option<TokenGuild>
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenGuild {
+    id: String
+}
+
+/**
+ * State bound to browser with nonce from cookie, so callback cannot be forged by other site
+ * Format is `{nonce}.{expires_at}.{signature}`
+ */
+pub fn make_state(secret: &str, nonce: &str, now: SystemTime) -> String {
+    let expires_at = (now + STATE_TTL).duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    format!("{}.{}.{}", nonce, expires_at, state_signature(secret, nonce, expires_at))
+}
+
+pub fn verify_state(secret: &str, state: &str, nonce: &str, now: SystemTime) -> bool {
+    let [state_nonce, expires_at, signature] = state.split('.').collect::<Vec<_>>()[..] else {
+        return false;
+    };
+
+    let Ok(expires_at) = expires_at.parse::<u64>() else {
+        return false;
+    };
+
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    state_nonce == nonce
+        && now <= expires_at
+        && signature == state_signature(secret, state_nonce, expires_at)
+}
+
+fn state_signature(secret: &str, nonce: &str, expires_at: u64) -> String {
+    webhooks::signature(secret, &format!("oauth:{}:{}", nonce, expires_at))
+}
+
+pub fn new_nonce() -> String {
+    utils::random_token()
+}
+
+/**
+ * Discord page where GM chooses guild to add bot to
+ */
+pub fn authorize_url(conf: &OAuthConf, state: &str) -> Result<String> {
+    let url = Url::parse_with_params("https://discord.com/oauth2/authorize", &[
+        ("client_id", conf.client_id.to_string()),
+        ("scope", "bot applications.commands".to_string()),
+        ("permissions", BOT_PERMISSIONS.bits().to_string()),
+        ("response_type", "code".to_string()),
+        ("redirect_uri", conf.redirect_url.clone()),
+        ("state", state.to_string()),
+    ])?;
+
+    Ok(url.to_string())
+}
+
+/**
+ * Exchanges authorization code for token and returns guild bot was added to
+ * Guild comes from Discord, so it cannot be substituted by caller
+ */
+pub async fn exchange_code(client: &reqwest::Client, conf: &OAuthConf, code: &str) -> Result<u64> {
+    let res = client.post(format!("{}/oauth2/token", conf.api_url.trim_end_matches('/')))
+        .basic_auth(conf.client_id, Some(&conf.client_secret))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &conf.redirect_url),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let token: TokenResponse = serde_json::from_slice(&res.bytes().await?)?;
+    let guild = token.guild.ok_or(anyhow!("Token response has no guild"))?;
+
+    Ok(guild.id.parse()?)
+}
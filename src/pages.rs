@@ -0,0 +1,261 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, Response, StatusCode};
+use indoc::formatdoc;
+use querystring::querify;
+
+use crate::{api::Body, db::{Dao, HistoryEntry, Scope, SignInfo, SignState}, signs::{self, Pack}, webhooks};
+
+const CHRONICLE_LIMIT: i64 = 50;
+
+static LINKS: OnceLock<Links> = OnceLock::new();
+
+/**
+ * Where pages are served and key to sign links to them
+ */
+struct Links {
+    public_url: String,
+    secret: String
+}
+
+/**
+ * Enables links to pages, without it pages are not served
+ */
+pub fn init(public_url: String, secret: String) -> Result<()> {
+    let links = Links { public_url: public_url.trim_end_matches('/').to_string(), secret };
+
+    if LINKS.set(links).is_err() {
+        return Err(anyhow::anyhow!("Pages are already inited"));
+    }
+    Ok(())
+}
+
+/**
+ * Link to page with current sign of scope, players open it without Discord
+ * Link carries key, so only guild members who got it from bot can see the page
+ */
+pub fn link(scope: Scope) -> Option<String> {
+    let links = LINKS.get()?;
+
+    Some(format!("{}/pages/{}?{}", links.public_url, scope.guild_id, link_query(links, scope)))
+}
+
+fn link_query(links: &Links, scope: Scope) -> String {
+    format!("campaign_id={}&key={}", scope.campaign_id, key(links, scope))
+}
+
+fn key(links: &Links, scope: Scope) -> String {
+    webhooks::signature(&links.secret, &format!("pages:{}:{}", scope.guild_id, scope.campaign_id))
+}
+
+/**
+ * Signature of payload with key of links, so other pages can be linked from bot too
+ */
+pub fn sign(payload: &str) -> Option<String> {
+    LINKS.get().map(|links| webhooks::signature(&links.secret, payload))
+}
+
+pub fn public_url() -> Option<&'static str> {
+    LINKS.get().map(|links| links.public_url.as_str())
+}
+
+/**
+ * Server-rendered pages of guild, hidden signs are never shown
+ *
+ * `GET /pages/{guild_id}?campaign_id={campaign_id}&key={key}` - current signs
+ * `GET /pages/{guild_id}/chronicle?campaign_id={campaign_id}&key={key}` - past signs with outcomes
+ */
+pub async fn handle<B>(dao: &dyn Dao, req: &Request<B>) -> Result<Response<Body>> {
+    let Some(links) = LINKS.get() else {
+        return page(StatusCode::NOT_FOUND, "Не найдено", "<p>Страницы знамений не настроены.</p>");
+    };
+
+    if req.method() != Method::GET {
+        return page(StatusCode::METHOD_NOT_ALLOWED, "Ошибка", "<p>Метод не поддерживается.</p>");
+    }
+
+    let segments = req.uri().path().trim_matches('/').split('/').collect::<Vec<_>>();
+    let (guild_id, chronicle) = match segments.as_slice() {
+        ["pages", guild_id] => (guild_id, false),
+        ["pages", guild_id, "chronicle"] => (guild_id, true),
+        _ => return not_found(),
+    };
+
+    let query: HashMap<&str, &str> = querify(req.uri().query().unwrap_or("")).into_iter().collect();
+
+    let Ok(guild_id) = guild_id.parse::<u64>() else {
+        return not_found();
+    };
+    let Ok(campaign_id) = query.get("campaign_id").unwrap_or(&"0").parse::<i64>() else {
+        return not_found();
+    };
+
+    let scope = Scope::campaign(guild_id, campaign_id);
+
+    // Same answer as for missing page, so guild ids cannot be probed
+    if query.get("key") != Some(&key(links, scope).as_str()) {
+        return not_found();
+    }
+
+    let pack = signs::scope_pack(dao, scope).await?;
+
+    if chronicle {
+        let history = dao.get_sign_history(scope, CHRONICLE_LIMIT).await?;
+        let current = format!("/pages/{}?{}", guild_id, link_query(links, scope));
+
+        return page(StatusCode::OK, "Летопись знамений", &render_chronicle(pack, &history, &current));
+    }
+
+    let signs = dao.get_guild_info(scope).await?
+        .map(|g| g.signs)
+        .unwrap_or_default();
+    let chronicle = format!("/pages/{}/chronicle?{}", guild_id, link_query(links, scope));
+
+    page(StatusCode::OK, "Знамение дня", &render_current(pack, &signs, &chronicle))
+}
+
+fn render_current(pack: &Pack, signs: &[SignInfo], chronicle_link: &str) -> String {
+    let cards = signs.iter()
+        .map(|s| if s.hidden {
+            "<section class=\"card\"><h2>Знамение скрыто</h2><p><em>Мастер откроет его позже</em></p></section>".to_string()
+        } else {
+            render_card(pack, s)
+        })
+        .collect::<String>();
+
+    let cards = if cards.is_empty() {
+        "<p>Сегодня еще не было знамения.</p>".to_string()
+    } else {
+        cards
+    };
+
+    format!("<h1>Знамение дня</h1>{}<p><a href=\"{}\">Летопись знамений</a></p>", cards, escape(chronicle_link))
+}
+
+fn render_card(pack: &Pack, sign: &SignInfo) -> String {
+    let Some(data) = pack.get_sign(&sign.id) else {
+        return String::new();
+    };
+
+    let effects = match sign.state {
+        SignState::Created => {
+            let mut effects = vec![("Успех", &data.success_effect), ("Провал", &data.failure_effect)];
+            if let Some(effect) = &data.critical_success_effect {
+                effects.push(("Критический успех", effect));
+            }
+            if let Some(effect) = &data.critical_failure_effect {
+                effects.push(("Критический провал", effect));
+            }
+            effects
+        },
+        SignState::Success { .. } => vec![("Эффект после изменения", &data.success_effect)],
+        SignState::Failed { .. } => vec![("Эффект после изменения", &data.failure_effect)],
+        SignState::CriticalSuccess { .. } =>
+            vec![("Эффект после изменения", data.critical_success_effect.as_ref().unwrap_or(&data.success_effect))],
+        SignState::CriticalFailure { .. } =>
+            vec![("Эффект после изменения", data.critical_failure_effect.as_ref().unwrap_or(&data.failure_effect))],
+    };
+
+    let effects = effects.iter()
+        .map(|(title, effect)| format!("<p><strong>{}:</strong> {}</p>", title, escape(effect)))
+        .collect::<String>();
+
+    let unmodifiable = if sign.modifiable { "" } else { "<p><em>Это знамение нельзя изменить</em></p>" };
+
+    formatdoc!(r#"
+        <section class="card">
+        <h2>{}</h2>
+        <p class="state">{}</p>
+        <p><strong>Кости:</strong> {} <strong>Сложность:</strong> {}</p>
+        <blockquote>{}</blockquote>
+        <p><strong>Эффект:</strong> {}</p>
+        {}{}
+        </section>"#,
+        escape(&data.name), render_state(&sign.state), escape(&data.id), data.difficulty,
+        escape(&data.description), escape(&data.effect), effects, unmodifiable
+    )
+}
+
+fn render_chronicle(pack: &Pack, history: &[HistoryEntry], current_link: &str) -> String {
+    let rows = history.iter()
+        .filter(|e| !e.sign.hidden)
+        .map(|e| format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            render_date(&e.sign),
+            escape(&pack.get_sign(&e.sign.id).map(|d| d.name.clone()).unwrap_or(e.sign.id.clone())),
+            if e.discarded { "Отброшено" } else { render_state(&e.sign.state) }
+        ))
+        .collect::<String>();
+
+    let table = if rows.is_empty() {
+        "<p>Знамений еще не было.</p>".to_string()
+    } else {
+        format!("<table><tr><th>Дата</th><th>Знамение</th><th>Исход</th></tr>{}</table>", rows)
+    };
+
+    format!("<h1>Летопись знамений</h1>{}<p><a href=\"{}\">Знамение дня</a></p>", table, escape(current_link))
+}
+
+fn render_state(state: &SignState) -> &'static str {
+    match state {
+        SignState::Created => "Не изменено",
+        SignState::Success { .. } => "Успех",
+        SignState::Failed { .. } => "Провал",
+        SignState::CriticalSuccess { .. } => "Критический успех",
+        SignState::CriticalFailure { .. } => "Критический провал",
+    }
+}
+
+fn render_date(sign: &SignInfo) -> String {
+    DateTime::<Utc>::from(sign.created_at).format("%d.%m.%Y").to_string()
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn not_found() -> Result<Response<Body>> {
+    page(StatusCode::NOT_FOUND, "Не найдено", "<p>Страница не найдена.</p>")
+}
+
+/**
+ * Full HTML page with common style
+ */
+pub fn page(status: StatusCode, title: &str, content: &str) -> Result<Response<Body>> {
+    let html = formatdoc!(r#"
+        <!DOCTYPE html>
+        <html lang="ru">
+        <head>
+        <meta charset="utf-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1">
+        <title>{}</title>
+        <style>
+        body {{ font-family: sans-serif; max-width: 720px; margin: 2em auto; padding: 0 1em; background: #1e1f22; color: #dbdee1; }}
+        a {{ color: #00a8fc; }}
+        .card {{ background: #2b2d31; border-radius: 8px; padding: 1em 1.5em; margin-bottom: 1em; }}
+        .state {{ color: #f0b232; }}
+        .error {{ color: #f23f43; }}
+        blockquote {{ font-style: italic; border-left: 4px solid #4e5058; margin: 0; padding-left: 1em; }}
+        table {{ width: 100%; border-collapse: collapse; }}
+        td, th {{ text-align: left; padding: 0.4em; border-bottom: 1px solid #4e5058; }}
+        </style>
+        </head>
+        <body>
+        {}
+        </body>
+        </html>
+        "#, escape(title), content
+    );
+
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(Full::new(html.into()).boxed_unsync())?)
+}
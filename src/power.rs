@@ -0,0 +1,192 @@
+use std::{str::FromStr, time::{Duration, SystemTime}};
+
+use anyhow::anyhow;
+
+use crate::db::{GuildSettings, UserInfo};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/**
+ * Title of shaman with power not less than `min_power`
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Rank {
+    pub min_power: i32,
+    pub title: String
+}
+
+/**
+ * Ranks in `min_power:title` format separated by commas, sorted by power
+ * `0:Ученик,10:Шаман,16:Верховный шаман`
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Ranks(pub Vec<Rank>);
+
+impl Ranks {
+    /**
+     * Highest rank reached with given power
+     */
+    pub fn get(&self, power: i32) -> Option<&Rank> {
+        self.0.iter().rev().find(|r| power >= r.min_power)
+    }
+}
+
+impl Default for Ranks {
+    fn default() -> Self {
+        "0:Ученик,10:Шаман,16:Верховный шаман".parse().unwrap()
+    }
+}
+
+impl FromStr for Ranks {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ranks = parse_thresholds(s)?.into_iter()
+            .map(|(min_power, title)| Rank { min_power, title: title.to_string() })
+            .collect();
+
+        Ok(Ranks(ranks))
+    }
+}
+
+impl std::fmt::Display for Ranks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ranks = self.0.iter()
+            .map(|r| format!("{}:{}", r.min_power, r.title))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        write!(f, "{}", ranks)
+    }
+}
+
+/**
+ * Discord role given to shamans with power not less than `min_power`
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RankRole {
+    pub min_power: i32,
+    pub role_id: u64
+}
+
+/**
+ * Rank roles in `min_power:role_id` format separated by commas, sorted by power
+ */
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RankRoles(pub Vec<RankRole>);
+
+impl RankRoles {
+    /**
+     * Role of highest rank reached with given power
+     */
+    pub fn get(&self, power: i32) -> Option<u64> {
+        self.0.iter().rev().find(|r| power >= r.min_power).map(|r| r.role_id)
+    }
+
+    /**
+     * Maps power threshold to role, replacing previous threshold of this role and previous role of this threshold
+     */
+    pub fn set(&mut self, min_power: i32, role_id: u64) {
+        self.0.retain(|r| r.min_power != min_power && r.role_id != role_id);
+        self.0.push(RankRole { min_power, role_id });
+        self.0.sort_by_key(|r| r.min_power);
+    }
+
+    pub fn remove(&mut self, role_id: u64) -> bool {
+        let len = self.0.len();
+        self.0.retain(|r| r.role_id != role_id);
+        self.0.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for RankRoles {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let roles = parse_thresholds(s)?.into_iter()
+            .map(|(min_power, role_id)| Ok(RankRole { min_power, role_id: role_id.parse()? }))
+            .collect::<Result<Vec<_>, Self::Err>>()?;
+
+        Ok(RankRoles(roles))
+    }
+}
+
+impl std::fmt::Display for RankRoles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let roles = self.0.iter()
+            .map(|r| format!("{}:{}", r.min_power, r.role_id))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        write!(f, "{}", roles)
+    }
+}
+
+/**
+ * Parses `power:value` pairs separated by commas, sorted by power
+ */
+fn parse_thresholds(s: &str) -> anyhow::Result<Vec<(i32, &str)>> {
+    let mut thresholds = s.split(',')
+        .filter(|t| !t.trim().is_empty())
+        .map(|t| {
+            let (power, value) = t.split_once(':')
+                .ok_or(anyhow!(format!("{} has no power threshold", t)))?;
+
+            Ok((power.trim().parse()?, value.trim()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    thresholds.sort_by_key(|(power, _)| *power);
+
+    Ok(thresholds)
+}
+
+/**
+ * Power with title of its rank, if guild has ranks
+ */
+pub fn render_power(power: i32, settings: &GuildSettings) -> String {
+    match settings.ranks.get(power) {
+        Some(rank) => format!("{} ({})", power, rank.title),
+        None => power.to_string(),
+    }
+}
+
+/**
+ * Keeps power within guild bounds
+ */
+pub fn clamp(power: i32, settings: &GuildSettings) -> i32 {
+    power.clamp(settings.power_min, settings.power_max)
+}
+
+/**
+ * Moves power toward baseline by `power_decay` for every full day since last power change
+ * Unused part of the day is kept, so regression does not depend on how often power is checked
+ */
+pub fn regress(user_info: &mut UserInfo, settings: &GuildSettings, now: SystemTime) {
+    if settings.power_decay <= 0 {
+        user_info.power_updated_at = now;
+        return;
+    }
+
+    let elapsed = now.duration_since(user_info.power_updated_at).unwrap_or_default();
+    let days = (elapsed.as_secs() / DAY.as_secs()) as u32;
+
+    if days == 0 {
+        return;
+    }
+
+    let step = settings.power_decay.saturating_mul(days.try_into().unwrap_or(i32::MAX));
+    let power = user_info.shaman_power;
+    let baseline = settings.power_baseline;
+
+    user_info.shaman_power = if power > baseline {
+        (power - step).max(baseline)
+    } else {
+        (power + step).min(baseline)
+    };
+    user_info.power_updated_at += DAY * days;
+}
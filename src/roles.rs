@@ -0,0 +1,110 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use anyhow::Result;
+use log::{error, info};
+use serenity::all::{GuildId, Http, Member, RoleId};
+
+use crate::{db::{Dao, GuildSettings, Scope, UserInfo}, power::{self, RankRoles}};
+
+const MEMBERS_PAGE: u64 = 1000;
+
+/**
+ * Result of syncing rank roles of all guild members
+ */
+#[derive(Debug, Default)]
+pub struct SyncStats {
+    pub changed: usize,
+    pub failed: usize
+}
+
+/**
+ * Gives player role of rank reached with current shaman power, called after power change
+ * Rank roles are guild-wide, so power in campaigns does not change them
+ */
+pub async fn sync_user(http: &Http, settings: &GuildSettings, user_info: &UserInfo) -> Result<()> {
+    if settings.campaign_id != 0 || settings.rank_roles.is_empty() {
+        return Ok(());
+    }
+
+    let member = http.get_member(GuildId::new(settings.guild_id), user_info.id.into()).await?;
+    sync_member(http, &settings.rank_roles, &member, Some(user_info.shaman_power)).await?;
+
+    Ok(())
+}
+
+/**
+ * Syncs rank roles of all guild members with their shaman power in guild scope
+ * Members who never interacted with signs lose rank roles
+ */
+pub async fn sync_guild(dao: &dyn Dao, http: &Http, settings: &GuildSettings) -> Result<SyncStats> {
+    let mut stats = SyncStats::default();
+
+    if settings.rank_roles.is_empty() {
+        return Ok(stats);
+    }
+
+    let now = SystemTime::now();
+    let powers = dao.get_users(Scope::guild(settings.guild_id)).await?.into_iter()
+        .map(|mut u| {
+            power::regress(&mut u, settings, now);
+            (u.id, u.shaman_power)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let guild_id = GuildId::new(settings.guild_id);
+    let mut after = None;
+
+    loop {
+        let members = http.get_guild_members(guild_id, Some(MEMBERS_PAGE), after).await?;
+
+        for member in &members {
+            if member.user.bot {
+                continue;
+            }
+
+            let power = powers.get(&member.user.id.get()).copied();
+
+            match sync_member(http, &settings.rank_roles, member, power).await {
+                Ok(true) => stats.changed += 1,
+                Ok(false) => {},
+                Err(e) => {
+                    error!("Cannot sync rank roles of user {} in guild {}: {}", member.user.id, guild_id, e);
+                    stats.failed += 1;
+                },
+            }
+        }
+
+        if (members.len() as u64) < MEMBERS_PAGE {
+            break;
+        }
+        after = members.last().map(|m| m.user.id.get());
+    }
+
+    info!("Rank roles synced in guild {}: {:?}", guild_id, stats);
+
+    Ok(stats)
+}
+
+/**
+ * Adds role of reached rank and removes roles of other ranks
+ * Returns true if roles of member were changed
+ */
+async fn sync_member(http: &Http, rank_roles: &RankRoles, member: &Member, power: Option<i32>) -> Result<bool> {
+    let target = power.and_then(|p| rank_roles.get(p));
+    let mut changed = false;
+
+    for role in &rank_roles.0 {
+        let role_id = RoleId::new(role.role_id);
+        let has_role = member.roles.contains(&role_id);
+
+        if Some(role.role_id) == target && !has_role {
+            http.add_member_role(member.guild_id, member.user.id, role_id, Some("Shaman rank reached")).await?;
+            changed = true;
+        } else if Some(role.role_id) != target && has_role {
+            http.remove_member_role(member.guild_id, member.user.id, role_id, Some("Shaman rank changed")).await?;
+            changed = true;
+        }
+    }
+
+    Ok(changed)
+}
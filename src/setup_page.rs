@@ -0,0 +1,172 @@
+use std::{collections::HashMap, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use anyhow::Result;
+use http_body_util::BodyExt;
+use hyper::{body::Incoming, Method, Request, Response, StatusCode};
+use querystring::querify;
+use serde::Deserialize;
+use serenity::all::Http;
+
+use crate::{api::Body, commands::sign_setup::{self, GuildChoices, Setup}, db::{Cadence, Dao, GuildSettings, Scope}, pages::{self, escape}, signs};
+
+/// Time to finish setup after link is given
+const LINK_TTL: Duration = Duration::from_secs(3600);
+const CADENCES: [Cadence; 3] = [Cadence::Daily, Cadence::Manual, Cadence::ScheduledEvents];
+
+/**
+ * Submitted setup form, empty fields keep current settings
+ */
+#[derive(Debug, Deserialize)]
+struct SetupForm {
+    #[serde(default)]
+    sign_pack: String,
+    #[serde(default)]
+    channel_id: String,
+    #[serde(default)]
+    timezone: String,
+    #[serde(default)]
+    cadence: String,
+    #[serde(default)]
+    gm_role_id: String
+}
+
+/**
+ * Link to onboarding page of guild, it expires so leaked link does not give settings away
+ */
+pub fn link(guild_id: u64, now: SystemTime) -> Option<String> {
+    let expires_at = (now + LINK_TTL).duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    Some(format!("{}/setup/{}?{}", pages::public_url()?, guild_id, link_query(guild_id, expires_at)?))
+}
+
+fn link_query(guild_id: u64, expires_at: u64) -> Option<String> {
+    Some(format!("expires={}&key={}", expires_at, key(guild_id, expires_at)?))
+}
+
+fn key(guild_id: u64, expires_at: u64) -> Option<String> {
+    pages::sign(&format!("setup:{}:{}", guild_id, expires_at))
+}
+
+/**
+ * Onboarding page opened after bot installation, same choices as `/sign_setup`
+ *
+ * `GET /setup/{guild_id}?expires={expires_at}&key={key}` - form with current settings
+ * `POST /setup/{guild_id}?expires={expires_at}&key={key}` - saves form
+ */
+pub async fn handle(dao: &dyn Dao, http: &Http, req: Request<Incoming>) -> Result<Response<Body>> {
+    let segments = req.uri().path().trim_matches('/').split('/').collect::<Vec<_>>();
+    let ["setup", guild_id] = segments.as_slice() else {
+        return not_found();
+    };
+
+    let Ok(guild_id) = guild_id.parse::<u64>() else {
+        return not_found();
+    };
+
+    let query: HashMap<&str, &str> = querify(req.uri().query().unwrap_or("")).into_iter().collect();
+    let expires_at = query.get("expires").and_then(|e| e.parse::<u64>().ok()).unwrap_or_default();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    // Same answer as for missing page, so guild ids cannot be probed
+    let Some(expected_key) = key(guild_id, expires_at) else {
+        return not_found();
+    };
+
+    if query.get("key") != Some(&expected_key.as_str()) {
+        return not_found();
+    }
+
+    if now > expires_at {
+        return pages::page(StatusCode::FORBIDDEN, "Настройка бота", "<h1>Настройка бота</h1><p>Ссылка устарела, получите новую командой /sign_setup.</p>");
+    }
+
+    let action = format!("/setup/{}?{}", guild_id, link_query(guild_id, expires_at).unwrap_or_default());
+    let installed = query.contains_key("installed");
+    let choices = sign_setup::guild_choices(http, guild_id).await?;
+
+    let (settings, message) = if req.method() == Method::POST {
+        let body = req.collect().await?.to_bytes();
+        let setup = serde_urlencoded::from_bytes::<SetupForm>(&body)
+            .map_err(|_| "Форма заполнена неверно".to_string())
+            .and_then(parse_form);
+
+        let res = match setup {
+            Ok(setup) => sign_setup::apply(dao, guild_id, &choices, setup).await?,
+            Err(msg) => Err(msg),
+        };
+
+        match res {
+            Ok(settings) => (settings, "<p class=\"state\">Настройки сохранены.</p>".to_string()),
+            Err(msg) => (current_settings(dao, guild_id).await?, format!("<p class=\"error\">{}</p>", escape(&msg))),
+        }
+    } else {
+        let message = if installed { "<p class=\"state\">Бот добавлен на сервер! Осталось его настроить.</p>" } else { "" };
+        (current_settings(dao, guild_id).await?, message.to_string())
+    };
+
+    let form = render_form(&choices, &settings, &action);
+
+    pages::page(StatusCode::OK, "Настройка бота", &format!("<h1>Настройка бота</h1>{}{}", message, form))
+}
+
+async fn current_settings(dao: &dyn Dao, guild_id: u64) -> Result<GuildSettings> {
+    let scope = Scope::guild(guild_id);
+
+    Ok(dao.get_guild_settings(scope).await?.unwrap_or(GuildSettings::new(scope)))
+}
+
+/**
+ * Returns message for user if some field cannot be parsed
+ */
+fn parse_form(form: SetupForm) -> Result<Setup, String> {
+    let non_empty = |s: String| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+
+    Ok(Setup {
+        sign_pack: non_empty(form.sign_pack),
+        channel_id: non_empty(form.channel_id).map(|c| c.parse()).transpose()
+            .map_err(|_| "Канал выбран неверно".to_string())?,
+        timezone: non_empty(form.timezone),
+        cadence: non_empty(form.cadence).map(|c| c.parse()).transpose()
+            .map_err(|_| "Расписание выбрано неверно".to_string())?,
+        gm_role_id: non_empty(form.gm_role_id).map(|r| r.parse()).transpose()
+            .map_err(|_| "Роль ГМ выбрана неверно".to_string())?
+    })
+}
+
+fn render_form(choices: &GuildChoices, settings: &GuildSettings, action: &str) -> String {
+    let current_pack = signs::get_pack(settings.sign_pack.as_deref()).name.as_str();
+    let packs = signs::pack_names().into_iter()
+        .map(|p| option(p, p, p == current_pack))
+        .collect::<String>();
+
+    let channels = choices.channels.iter()
+        .map(|(id, name)| option(&id.to_string(), &format!("#{}", name), settings.auto_roll_channel_id == Some(*id)))
+        .collect::<String>();
+
+    let cadences = CADENCES.iter()
+        .map(|c| option(c.as_str(), sign_setup::cadence_name(*c), settings.cadence == *c))
+        .collect::<String>();
+
+    let roles = choices.roles.iter()
+        .map(|(id, name)| option(&id.to_string(), name, settings.gm_role_id == Some(*id)))
+        .collect::<String>();
+
+    format!(r#"<form class="card" method="post" action="{}">
+        <p><label>Колода знамений<br><select name="sign_pack">{}</select></label></p>
+        <p><label>Канал знамений<br><select name="channel_id"><option value="">не выбран</option>{}</select></label></p>
+        <p><label>Часовой пояс<br><input name="timezone" placeholder="Europe/Moscow" value="{}"></label></p>
+        <p><label>Новое знамение<br><select name="cadence">{}</select></label></p>
+        <p><label>Роль ГМ<br><select name="gm_role_id"><option value="">только управляющие сервером</option>{}</select></label></p>
+        <p><button type="submit">Сохранить</button></p>
+        </form>"#,
+        escape(action), packs, channels, escape(settings.timezone.as_deref().unwrap_or("")), cadences, roles
+    )
+}
+
+fn option(value: &str, title: &str, selected: bool) -> String {
+    format!("<option value=\"{}\"{}>{}</option>", escape(value), if selected { " selected" } else { "" }, escape(title))
+}
+
+fn not_found() -> Result<Response<Body>> {
+    pages::page(StatusCode::NOT_FOUND, "Не найдено", "<p>Страница не найдена.</p>")
+}
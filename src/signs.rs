@@ -1,35 +1,116 @@
 use indoc::formatdoc;
+use rand::Rng;
 use serde::Deserialize;
 
-use crate::db::SignInfo;
+use crate::{db::{Dao, Scope, SignInfo, SignState}, dice::RollMode};
 use anyhow::Result;
-use std::{fs, sync::OnceLock};
+use std::{fs, path::Path, str::FromStr, sync::OnceLock};
 use std::collections::HashMap;
 
-static DATA: OnceLock<HashMap<String, SignData>> = OnceLock::new();
+static PACKS: OnceLock<Packs> = OnceLock::new();
+
+/**
+ * Loaded sign packs by name
+ */
+struct Packs {
+    default: String,
+    packs: HashMap<String, Pack>
+}
+
+/**
+ * Signs of one pack by dice
+ */
+#[derive(Debug)]
+pub struct Pack {
+    pub name: String,
+    signs: HashMap<String, SignData>
+}
 
 #[derive(Debug, Clone, Deserialize)]
-struct SignData {
-    id: String,
-    name: String,
-    difficulty: i32,
-    description: String,
-    effect: String,
-    success_effect: String,
-    failure_effect: String
+pub struct SignData {
+    pub id: String,
+    pub name: String,
+    pub difficulty: i32,
+    pub description: String,
+    pub effect: String,
+    pub success_effect: String,
+    pub failure_effect: String,
+    #[serde(default)]
+    pub critical_success_effect: Option<String>,
+    #[serde(default)]
+    pub critical_failure_effect: Option<String>,
+    #[serde(default)]
+    pub success_outcome: Outcome,
+    #[serde(default)]
+    pub failure_outcome: Outcome,
+    #[serde(default)]
+    pub critical_success_outcome: Option<Outcome>,
+    #[serde(default)]
+    pub critical_failure_outcome: Option<Outcome>,
+    #[serde(default)]
+    pub modify_roll_mode: RollMode
 }
 
-pub fn load_signs(file_path: String) -> Result<()> {
-    let data = fs::read_to_string(file_path)?;
-    let data: Vec<SignData> = serde_json::from_str(&data)?;
+/**
+ * Machine readable part of sign effect, applied by bot after sign modification
+ */
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Outcome {
+    #[serde(default)]
+    pub luck_dice: i32,
+    #[serde(default)]
+    pub next_roll: Option<NextRoll>
+}
 
-    let mut values = HashMap::new();
+/**
+ * Special rule for the next sign roll in guild
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NextRoll {
+    /// Two signs are rolled and roller chooses one of them
+    ChooseTwo,
+    /// Two signs are active at once and none of them can be modified
+    TwoUnmodifiable
+}
 
-    for s in data {
-        values.insert(s.id.clone(), s);
+impl NextRoll {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NextRoll::ChooseTwo => "choose_two",
+            NextRoll::TwoUnmodifiable => "two_unmodifiable",
+        }
     }
+}
+
+impl FromStr for NextRoll {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "choose_two" => Ok(NextRoll::ChooseTwo),
+            "two_unmodifiable" => Ok(NextRoll::TwoUnmodifiable),
+            s => Err(anyhow::anyhow!(format!("Unknown next roll {}", s))),
+        }
+    }
+}
 
-    let res = DATA.set(values);
+/**
+ * Loads sign packs, pack is named by file name without extension
+ * First pack is used by guilds and campaigns which have not chosen one
+ */
+pub fn load_signs(file_paths: Vec<String>) -> Result<()> {
+    let mut packs = HashMap::new();
+    let mut default = None;
+
+    for file_path in file_paths {
+        let pack = load_pack(&file_path)?;
+        default.get_or_insert(pack.name.clone());
+        packs.insert(pack.name.clone(), pack);
+    }
+
+    let default = default.ok_or(anyhow::anyhow!("No sign packs to load"))?;
+    let res = PACKS.set(Packs { default, packs });
 
     if res.is_err() {
         return Err(anyhow::anyhow!("Cannot load data from file"));
@@ -37,40 +118,195 @@ pub fn load_signs(file_path: String) -> Result<()> {
     Ok(())
 }
 
-pub fn render_sign(sign: SignInfo) -> String {
-    let sign_desc = DATA.get().unwrap().get(&sign.id).unwrap();
-
-    let mut res = formatdoc!(r#"
-    __**{}**__
-    **Кости:** {}
-    **Сложность:** {}
-
-    > *{}*
-
-    **Эффект:** {}
-    "#, sign_desc.name, sign_desc.id, sign_desc.difficulty, sign_desc.description, sign_desc.effect);
-
-    match sign.state {
-        crate::db::SignState::Created => res.push_str(&formatdoc!(r#"
-            **Успех:** {}
-            **Провал:** {}
-            "#, sign_desc.success_effect, sign_desc.failure_effect)
-        ),
-        crate::db::SignState::Success { by_user_id: _ } => res.push_str(&formatdoc!(r#"
-            **Эффект после изменения:** {}
-            "#, sign_desc.success_effect)
-        ),
-        crate::db::SignState::Failed { by_user_id: _ } => res.push_str(&formatdoc!(r#"
-            **Эффект после изменения:** {}
-            "#, sign_desc.failure_effect)
-        ),
+pub fn load_pack(file_path: &str) -> Result<Pack> {
+    let name = Path::new(file_path).file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let data = fs::read_to_string(file_path)?;
+    let data: Vec<SignData> = serde_json::from_str(&data)?;
+
+    let mut signs = HashMap::new();
+
+    for s in data {
+        signs.insert(s.id.clone(), s);
+    }
+
+    Ok(Pack { name, signs })
+}
+
+/**
+ * Pack with given name, default pack if name is not set or such pack is not loaded
+ */
+pub fn get_pack(name: Option<&str>) -> &'static Pack {
+    let packs = PACKS.get().unwrap();
+
+    name.and_then(|n| packs.packs.get(n))
+        .unwrap_or(&packs.packs[&packs.default])
+}
+
+/**
+ * Pack used in scope: pack of campaign, then pack of guild, then default one
+ */
+pub async fn scope_pack(dao: &dyn Dao, scope: Scope) -> Result<&'static Pack> {
+    Ok(get_pack(dao.get_sign_pack(scope).await?.as_deref()))
+}
+
+/**
+ * Names of loaded packs, default one goes first
+ */
+pub fn pack_names() -> Vec<&'static str> {
+    let Some(packs) = PACKS.get() else {
+        return vec![];
     };
 
-    res
+    let mut names: Vec<&str> = packs.packs.keys().map(|n| n.as_str()).collect();
+    names.sort_by_key(|n| (*n != packs.default, *n));
+
+    names
+}
+
+pub fn has_pack(name: &str) -> bool {
+    PACKS.get().is_some_and(|p| p.packs.contains_key(name))
 }
 
-pub fn get_difficulty(sign_id: String) -> i32 {
-    let sign = DATA.get().unwrap().get(&sign_id).unwrap();
+impl Pack {
+    pub fn render_sign(&self, sign: &SignInfo) -> String {
+        let sign_desc = self.signs.get(&sign.id).unwrap();
 
-    sign.difficulty
-}
\ No newline at end of file
+        let mut res = formatdoc!(r#"
+        __**{}**__
+        **Кости:** {}
+        **Сложность:** {}
+
+        > *{}*
+
+        **Эффект:** {}
+        "#, sign_desc.name, sign_desc.id, sign_desc.difficulty, sign_desc.description, sign_desc.effect);
+
+        match sign.state {
+            crate::db::SignState::Created => {
+                res.push_str(&formatdoc!(r#"
+                    **Успех:** {}
+                    **Провал:** {}
+                    "#, sign_desc.success_effect, sign_desc.failure_effect)
+                );
+                if let Some(effect) = &sign_desc.critical_success_effect {
+                    res.push_str(&format!("**Критический успех:** {}\n", effect));
+                }
+                if let Some(effect) = &sign_desc.critical_failure_effect {
+                    res.push_str(&format!("**Критический провал:** {}\n", effect));
+                }
+            },
+            crate::db::SignState::Success { by_user_id: _ } => res.push_str(&formatdoc!(r#"
+                **Эффект после изменения:** {}
+                "#, sign_desc.success_effect)
+            ),
+            crate::db::SignState::Failed { by_user_id: _ } => res.push_str(&formatdoc!(r#"
+                **Эффект после изменения:** {}
+                "#, sign_desc.failure_effect)
+            ),
+            crate::db::SignState::CriticalSuccess { by_user_id: _ } => res.push_str(&formatdoc!(r#"
+                __**Критический успех!**__
+                **Эффект после изменения:** {}
+                "#, sign_desc.critical_success_effect.as_ref().unwrap_or(&sign_desc.success_effect))
+            ),
+            crate::db::SignState::CriticalFailure { by_user_id: _ } => res.push_str(&formatdoc!(r#"
+                __**Критический провал!**__
+                **Эффект после изменения:** {}
+                "#, sign_desc.critical_failure_effect.as_ref().unwrap_or(&sign_desc.failure_effect))
+            ),
+        };
+
+        if !sign.modifiable {
+            res.push_str("*Это знамение нельзя изменить*\n");
+        }
+
+        if sign.hidden {
+            res.push_str("*Знамение скрыто от игроков*\n");
+        }
+
+        res
+    }
+
+    /**
+     * Roll mode which active sign imposes on its modification
+     */
+    pub fn get_modify_roll_mode(&self, sign_id: &str) -> RollMode {
+        self.signs.get(sign_id).unwrap().modify_roll_mode
+    }
+
+    pub fn get_sign(&self, sign_id: &str) -> Option<&SignData> {
+        self.signs.get(sign_id)
+    }
+
+    pub fn get_name(&self, sign_id: &str) -> String {
+        self.signs.get(sign_id).unwrap().name.clone()
+    }
+
+    pub fn get_difficulty(&self, sign_id: &str) -> i32 {
+        let sign = self.signs.get(sign_id).unwrap();
+
+        sign.difficulty
+    }
+
+    /**
+     * All signs of pack ordered by dice
+     */
+    pub fn list_signs(&self) -> Vec<&SignData> {
+        let mut signs: Vec<&SignData> = self.signs.values().collect();
+        signs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        signs
+    }
+
+    /**
+     * Outcome of sign in given state
+     * Critical outcomes fall back to regular ones if pack does not define them
+     */
+    pub fn get_outcome(&self, sign_id: &str, state: &SignState) -> Outcome {
+        let sign = self.signs.get(sign_id).unwrap();
+
+        match state {
+            SignState::Created => Outcome::default(),
+            SignState::Success { by_user_id: _ } => sign.success_outcome.clone(),
+            SignState::Failed { by_user_id: _ } => sign.failure_outcome.clone(),
+            SignState::CriticalSuccess { by_user_id: _ } => sign.critical_success_outcome.clone()
+                .unwrap_or(sign.success_outcome.clone()),
+            SignState::CriticalFailure { by_user_id: _ } => sign.critical_failure_outcome.clone()
+                .unwrap_or(sign.failure_outcome.clone()),
+        }
+    }
+}
+
+/**
+ * Rolls 4d4 and returns sorted dice as sign id
+ */
+pub fn roll_sign_id() -> String {
+    let mut rand_seq = vec![];
+
+    for _ in 1..=4 {
+        rand_seq.push(rand::thread_rng().gen_range(1..=4).to_string());
+    }
+
+    rand_seq.sort();
+
+    rand_seq.join("")
+}
+
+/**
+ * Probability to roll sign with given dice in `roll_sign_id`
+ * Sorted 4d4 come from 4! / (n1! * n2! * ...) ordered rolls of 4^4
+ */
+pub fn sign_probability(sign_id: &str) -> f64 {
+    let factorial = |n: usize| (1..=n).product::<usize>() as f64;
+
+    let mut counts = HashMap::new();
+    for die in sign_id.chars() {
+        *counts.entry(die).or_insert(0) += 1;
+    }
+
+    let orders = counts.values().fold(factorial(sign_id.len()), |acc, n| acc / factorial(*n));
+
+    orders / 4f64.powi(sign_id.len() as i32)
+}
@@ -1,7 +1,14 @@
-use anyhow::Result;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Pool;
 use testcontainers_modules::{postgres::Postgres, testcontainers::clients::Cli};
-use crate::db::{psql, Dao, SignState, UserInfo};
+use tokio_postgres::NoTls;
+use crate::{api, commands::sign_setup::{self, GuildChoices, Setup}, db::{psql, Cadence, Dao, GuildSettings, ModifyRoll, Scope, SignInfo, SignState, Streaks, UserInfo, WebhookEvent}, dice::RollMode, signs::{self, NextRoll}, webhooks};
 
+fn no_events(_: &[SignInfo]) -> Result<Vec<WebhookEvent>> {
+    Ok(vec![])
+}
 
 // Global test scenario to reuse running psql container
 // It is ugly, but rust not supports global state with cleanup
@@ -13,14 +20,18 @@ async fn test_psql() -> Result<()> {
 
     let node = docker.run(Postgres::default());
 
-    let dao = psql::init_with_config(deadpool_postgres::Config {
+    let cfg = deadpool_postgres::Config {
         user: Some("postgres".to_string()),
         password: Some("postgres".to_string()),
         dbname: Some("postgres".to_string()),
         host: Some("localhost".to_string()),
         port: Some(node.get_host_port_ipv4(5432)),
         ..Default::default()
-    }).await?;
+    };
+    let dao = psql::init_with_config(cfg.clone()).await?;
+
+    // Direct access for data which Dao does not allow to set, like past sessions
+    let pool = cfg.create_pool(NoTls)?;
 
     // Running scenarios
     test_create_sign(&dao).await.unwrap();
@@ -28,94 +39,765 @@ async fn test_psql() -> Result<()> {
     test_get_guild(&dao).await.unwrap();
     test_change_sign_state(&dao).await.unwrap();
     test_user_info(&dao).await.unwrap();
+    test_critical_sign_state(&dao).await.unwrap();
+    test_guild_settings(&dao).await.unwrap();
+    test_ritual(&dao).await.unwrap();
+    test_sign_history(&dao).await.unwrap();
+    test_multiple_signs(&dao).await.unwrap();
+    test_sessions(&dao).await.unwrap();
+    test_hidden_signs(&dao).await.unwrap();
+    test_campaigns(&dao).await.unwrap();
+    test_characters(&dao).await.unwrap();
+    test_power_rules(&dao).await.unwrap();
+    test_rank_roles(&dao).await.unwrap();
+    test_user_stats(&dao).await.unwrap();
+    test_guild_stats(&dao).await.unwrap();
+    test_achievements(&dao).await.unwrap();
+    test_webhooks(&dao).await.unwrap();
+    test_api_tokens(&dao).await.unwrap();
+    test_guild_setup(&dao, &pool).await.unwrap();
 
     Ok(())
 }
 
 
 async fn test_create_sign(dao: &impl Dao) -> Result<()> {
-    let guild = dao.create_sign(1, "sign".to_string(), 1).await?;
+    let guild = dao.create_sign(Scope::guild(1), "sign".to_string(), 1).await?;
 
     assert!(guild.is_some());
     let g = guild.unwrap();
     
     assert_eq!(1, g.guild_id);
-    assert_eq!("sign", g.current_sign.id);
-    assert_eq!(1, g.current_sign.created_by_user_id);
-    assert_eq!(SignState::Created, g.current_sign.state);
+    assert_eq!(1, g.signs.len());
+    assert_eq!("sign", g.signs[0].id);
+    assert_eq!(1, g.signs[0].created_by_user_id);
+    assert_eq!(SignState::Created, g.signs[0].state);
+    assert!(g.signs[0].modifiable);
     Ok(())
 }
 
 async fn test_multi_create_sign(dao: &impl Dao) -> Result<()> {
-    let guild = dao.create_sign(2, "sign".to_string(), 1).await?;
+    let guild = dao.create_sign(Scope::guild(2), "sign".to_string(), 1).await?;
 
     assert!(guild.is_some());
 
-    let guild2 = dao.create_sign(2, "sign".to_string(), 1).await?;
+    let guild2 = dao.create_sign(Scope::guild(2), "sign".to_string(), 1).await?;
     assert!(guild2.is_none());
 
     Ok(())
 }
 
 async fn test_get_guild(dao: &impl Dao) -> Result<()> {
-    let guild = dao.get_guild_info(3).await?;
+    let guild = dao.get_guild_info(Scope::guild(3)).await?;
 
     assert!(guild.is_none());
 
-    let guild2 = dao.create_sign(3, "sign".to_string(), 1).await?;
+    let guild2 = dao.create_sign(Scope::guild(3), "sign".to_string(), 1).await?;
     assert!(guild2.is_some());
 
-    let guild3 = dao.get_guild_info(3).await?;
+    let guild3 = dao.get_guild_info(Scope::guild(3)).await?;
     assert!(guild3.is_some());
 
     let g = guild3.unwrap();
     
     assert_eq!(3, g.guild_id);
-    assert_eq!("sign", g.current_sign.id);
-    assert_eq!(1, g.current_sign.created_by_user_id);
-    assert_eq!(SignState::Created, g.current_sign.state);
+    assert_eq!(1, g.signs.len());
+    assert_eq!("sign", g.signs[0].id);
+    assert_eq!(1, g.signs[0].created_by_user_id);
+    assert_eq!(SignState::Created, g.signs[0].state);
 
     Ok(())
 }
 
 async fn test_change_sign_state(dao: &impl Dao) -> Result<()> {
-    let g = dao.change_sign_state(4, SignState::Success { by_user_id: 2 }).await?;
+    let g = dao.change_sign_state(Scope::guild(4), 0, SignState::Success { by_user_id: 2 }, &no_events).await?;
     assert!(g.is_err());
     assert!(g.err().unwrap().is_none());
 
-    let g = dao.create_sign(4, "sign".to_string(), 1).await?;
+    let g = dao.create_sign(Scope::guild(4), "sign".to_string(), 1).await?;
     assert!(g.is_some());
+    let row_id = g.unwrap().signs[0].row_id;
 
-    let g = dao.change_sign_state(4, SignState::Success { by_user_id: 1 }).await?;
+    let g = dao.change_sign_state(Scope::guild(4), row_id, SignState::Success { by_user_id: 1 }, &no_events).await?;
     assert!(g.is_err());
 
-    let g = dao.change_sign_state(4, SignState::Success { by_user_id: 2 }).await?;
+    let g = dao.change_sign_state(Scope::guild(4), row_id, SignState::Success { by_user_id: 2 }, &no_events).await?;
     assert!(g.is_ok());
 
     let g = g.ok().unwrap();
-    assert_eq!(4, g.guild_id);
-    assert_eq!("sign", g.current_sign.id);
-    assert_eq!(1, g.current_sign.created_by_user_id);
-    assert_eq!(SignState::Success { by_user_id: 2 }, g.current_sign.state);
+    assert_eq!(row_id, g.row_id);
+    assert_eq!("sign", g.id);
+    assert_eq!(1, g.created_by_user_id);
+    assert_eq!(SignState::Success { by_user_id: 2 }, g.state);
 
-    let g = dao.change_sign_state(4, SignState::Success { by_user_id: 2 }).await?;
+    let g = dao.change_sign_state(Scope::guild(4), row_id, SignState::Success { by_user_id: 2 }, &no_events).await?;
     assert!(g.is_err());
+    assert_eq!(SignState::Success { by_user_id: 2 }, g.err().unwrap().unwrap().state);
 
     Ok(())
 }
 
 async fn test_user_info(dao: &impl Dao) -> Result<()> {
-    let u = dao.get_user_info(1, 1).await?;
+    let u = dao.get_user_info(1, Scope::guild(1)).await?;
     assert!(u.is_none());
 
-    dao.save_user_info(UserInfo {id: 1, guild_id: 1, shaman_power: 10}).await?;
+    dao.save_user_info(UserInfo { shaman_power: 10, luck_dice: 2, ..UserInfo::new(1, Scope::guild(1)) }).await?;
 
-    let u = dao.get_user_info(1, 1).await?;
+    let u = dao.get_user_info(1, Scope::guild(1)).await?;
     assert!(u.is_some());
 
     let u = u.unwrap();
     assert_eq!(1, u.id);
     assert_eq!(1, u.guild_id);
     assert_eq!(10, u.shaman_power);
+    assert_eq!(2, u.luck_dice);
+
+    // Saved user keeps luck dice given meanwhile
+    assert_eq!(Some(3), dao.add_luck_dice(1, Scope::guild(1), 1).await?);
+    dao.save_user_info(UserInfo { shaman_power: 12, ..u }).await?;
+    let u = dao.get_user_info(1, Scope::guild(1)).await?.unwrap();
+    assert_eq!(12, u.shaman_power);
+    assert_eq!(3, u.luck_dice);
+
+    // Dice cannot be spent below zero
+    assert_eq!(Some(0), dao.add_luck_dice(1, Scope::guild(1), -3).await?);
+    assert_eq!(None, dao.add_luck_dice(1, Scope::guild(1), -1).await?);
+    assert_eq!(0, dao.get_user_info(1, Scope::guild(1)).await?.unwrap().luck_dice);
+
+    // New user gets dice without saving info first
+    assert_eq!(None, dao.add_luck_dice(2, Scope::guild(1), -1).await?);
+    assert_eq!(Some(2), dao.add_luck_dice(2, Scope::guild(1), 2).await?);
+    assert_eq!(10, dao.get_user_info(2, Scope::guild(1)).await?.unwrap().shaman_power);
+
+    Ok(())
+}
+
+async fn test_critical_sign_state(dao: &impl Dao) -> Result<()> {
+    let g = dao.create_sign(Scope::guild(5), "sign".to_string(), 1).await?;
+    assert!(g.is_some());
+    let row_id = g.unwrap().signs[0].row_id;
+
+    let g = dao.change_sign_state(Scope::guild(5), row_id, SignState::CriticalFailure { by_user_id: 2 }, &no_events).await?;
+    assert!(g.is_ok());
+
+    let g = dao.get_guild_info(Scope::guild(5)).await?;
+    assert!(g.is_some());
+    assert_eq!(SignState::CriticalFailure { by_user_id: 2 }, g.unwrap().signs[0].state);
+
+    Ok(())
+}
+
+async fn test_guild_settings(dao: &impl Dao) -> Result<()> {
+    let s = dao.get_guild_settings(Scope::guild(1)).await?;
+    assert!(s.is_none());
+
+    dao.save_guild_settings(GuildSettings { modify_roll_mode: RollMode::Advantage, ..GuildSettings::new(Scope::guild(1)) }).await?;
+
+    let s = dao.get_guild_settings(Scope::guild(1)).await?;
+    assert!(s.is_some());
+
+    let s = s.unwrap();
+    assert_eq!(1, s.guild_id);
+    assert_eq!(RollMode::Advantage, s.modify_roll_mode);
+    assert!(s.auto_roll_channel_id.is_none());
+
+    dao.save_guild_settings(GuildSettings {
+        auto_roll_channel_id: Some(10),
+        auto_roll_event_name: Some("Сессия".to_string()),
+        ..s
+    }).await?;
+
+    let s = dao.get_guild_settings(Scope::guild(1)).await?.unwrap();
+    assert_eq!(Some(10), s.auto_roll_channel_id);
+    assert_eq!(Some("Сессия".to_string()), s.auto_roll_event_name);
+    assert!(s.auto_roll_event_channel_id.is_none());
+    assert!(s.event_matches("Вторая сессия", None));
+    assert!(!s.event_matches("Подготовка", None));
+    assert!(s.modify_role_ids.is_empty());
+
+    dao.save_guild_settings(GuildSettings { modify_role_ids: vec![100, 101], ..s }).await?;
+
+    let s = dao.get_guild_settings(Scope::guild(1)).await?.unwrap();
+    assert_eq!(vec![100, 101], s.modify_role_ids);
+    assert!(s.roll_role_ids.is_empty());
+    Ok(())
+}
+
+async fn test_ritual(dao: &impl Dao) -> Result<()> {
+    let r = dao.get_ritual(Scope::guild(1)).await?;
+    assert!(r.is_none());
+
+    let r = dao.create_ritual(Scope::guild(1), 7, 1, true, 10, 60).await?;
+    assert!(r.is_some());
+
+    let r = dao.create_ritual(Scope::guild(1), 7, 2, false, 10, 60).await?;
+    assert!(r.is_none());
+
+    dao.set_ritual_message(Scope::guild(1), 11).await?;
+
+    let r = dao.add_ritual_helper(Scope::guild(1), 1, 2).await?;
+    assert!(r.is_none());
+
+    let r = dao.add_ritual_helper(Scope::guild(1), 2, 2).await?;
+    assert!(r.is_some());
+
+    let r = dao.add_ritual_helper(Scope::guild(1), 2, 2).await?;
+    assert!(r.is_none());
+
+    let r = dao.add_ritual_helper(Scope::guild(1), 3, 2).await?;
+    assert!(r.is_some());
+
+    let r = dao.add_ritual_helper(Scope::guild(1), 4, 2).await?;
+    assert!(r.is_none());
+
+    let expired = dao.take_expired_rituals().await?;
+    assert!(expired.is_empty());
+
+    let r = dao.take_ritual(Scope::guild(1)).await?;
+    assert!(r.is_some());
+
+    let r = r.unwrap();
+    assert_eq!(7, r.sign_row_id);
+    assert_eq!(1, r.shaman_id);
+    assert!(r.use_luck);
+    assert_eq!(10, r.channel_id);
+    assert_eq!(Some(11), r.message_id);
+    assert_eq!(vec![2, 3], r.helpers);
+
+    let r = dao.take_ritual(Scope::guild(1)).await?;
+    assert!(r.is_none());
+
+    let r = dao.create_ritual(Scope::guild(2), 8, 1, false, 10, 0).await?;
+    assert!(r.is_some());
+
+    let expired = dao.take_expired_rituals().await?;
+    assert_eq!(1, expired.len());
+    assert_eq!(2, expired[0].guild_id);
+    assert!(expired[0].message_id.is_none());
+
+    Ok(())
+}
+
+async fn test_sign_history(dao: &impl Dao) -> Result<()> {
+    let h = dao.get_sign_history(Scope::guild(6), 10).await?;
+    assert!(h.is_empty());
+
+    let c = dao.save_candidates(Scope::guild(6), ("sign".to_string(), "other".to_string())).await?;
+    assert_eq!(("sign".to_string(), "other".to_string()), c);
+
+    // Pending candidates are kept until signs are created
+    let c = dao.save_candidates(Scope::guild(6), ("third".to_string(), "fourth".to_string())).await?;
+    assert_eq!(("sign".to_string(), "other".to_string()), c);
+
+    let g = dao.create_sign(Scope::guild(6), "sign".to_string(), 1).await?;
+    assert!(g.is_some());
+    let row_id = g.unwrap().signs[0].row_id;
+
+    let c = dao.save_candidates(Scope::guild(6), ("third".to_string(), "fourth".to_string())).await?;
+    assert_eq!(("third".to_string(), "fourth".to_string()), c);
+
+    dao.add_discarded_sign(Scope::guild(6), "other".to_string(), 1).await?;
+
+    let g = dao.change_sign_state(Scope::guild(6), row_id, SignState::Failed { by_user_id: 2 }, &no_events).await?;
+    assert!(g.is_ok());
+
+    let h = dao.get_sign_history(Scope::guild(6), 10).await?;
+    assert_eq!(2, h.len());
+
+    let active = h.iter().find(|e| !e.discarded).unwrap();
+    assert_eq!("sign", active.sign.id);
+    assert_eq!(SignState::Failed { by_user_id: 2 }, active.sign.state);
+
+    let discarded = h.iter().find(|e| e.discarded).unwrap();
+    assert_eq!("other", discarded.sign.id);
+    assert_eq!(SignState::Created, discarded.sign.state);
+
+    let r = dao.get_next_roll(Scope::guild(6)).await?;
+    assert!(r.is_none());
+
+    dao.set_next_roll(Scope::guild(6), Some(NextRoll::ChooseTwo)).await?;
+    let r = dao.get_next_roll(Scope::guild(6)).await?;
+    assert_eq!(Some(NextRoll::ChooseTwo), r);
+
+    dao.set_next_roll(Scope::guild(6), None).await?;
+    let r = dao.get_next_roll(Scope::guild(6)).await?;
+    assert!(r.is_none());
+
+    Ok(())
+}
+
+async fn test_multiple_signs(dao: &impl Dao) -> Result<()> {
+    let g = dao.create_signs(Scope::guild(7), vec!["first".to_string(), "second".to_string()], false, false, 1, &no_events).await?;
+    assert!(g.is_some());
+
+    let g = dao.create_sign(Scope::guild(7), "third".to_string(), 1).await?;
+    assert!(g.is_none());
+
+    let g = dao.get_guild_info(Scope::guild(7)).await?;
+    assert!(g.is_some());
+
+    let g = g.unwrap();
+    assert_eq!(2, g.signs.len());
+    assert_eq!("first", g.signs[0].id);
+    assert_eq!("second", g.signs[1].id);
+    assert!(g.signs.iter().all(|s| !s.modifiable));
+    assert!(g.first_modifiable().is_none());
+
+    let res = dao.change_sign_state(Scope::guild(7), g.signs[0].row_id, SignState::Success { by_user_id: 2 }, &no_events).await?;
+    assert!(res.is_err());
+    assert_eq!(SignState::Created, res.err().unwrap().unwrap().state);
+
     Ok(())
-}
\ No newline at end of file
+}
+
+async fn test_sessions(dao: &impl Dao) -> Result<()> {
+    let s = dao.get_current_session(Scope::guild(8)).await?;
+    assert!(s.is_none());
+
+    dao.save_guild_settings(GuildSettings { cadence: Cadence::ScheduledEvents, ..GuildSettings::new(Scope::guild(8)) }).await?;
+    assert_eq!(Cadence::ScheduledEvents, dao.get_guild_settings(Scope::guild(8)).await?.unwrap().cadence);
+
+    let g = dao.create_sign(Scope::guild(8), "first".to_string(), 1).await?;
+    assert!(g.is_some());
+
+    let s = dao.get_current_session(Scope::guild(8)).await?;
+    assert!(s.is_some());
+    assert!(s.unwrap().event_id.is_none());
+
+    let g = dao.create_sign(Scope::guild(8), "second".to_string(), 1).await?;
+    assert!(g.is_none());
+
+    let s = dao.start_session(Scope::guild(8), Some(42)).await?;
+    assert_eq!(Some(42), s.event_id);
+
+    let g = dao.get_guild_info(Scope::guild(8)).await?;
+    assert!(g.is_none());
+
+    let g = dao.create_sign(Scope::guild(8), "second".to_string(), 1).await?;
+    assert!(g.is_some());
+    assert_eq!("second", g.unwrap().signs[0].id);
+
+    let current = dao.get_current_session(Scope::guild(8)).await?;
+    assert_eq!(s.id, current.unwrap().id);
+
+    Ok(())
+}
+
+async fn test_hidden_signs(dao: &impl Dao) -> Result<()> {
+    let g = dao.create_signs(Scope::guild(9), vec!["secret".to_string()], true, true, 1, &no_events).await?;
+    assert!(g.is_some());
+
+    let g = dao.get_guild_info(Scope::guild(9)).await?.unwrap();
+    assert!(g.signs[0].hidden);
+    assert!(g.first_modifiable().is_none());
+
+    let res = dao.change_sign_state(Scope::guild(9), g.signs[0].row_id, SignState::Success { by_user_id: 2 }, &no_events).await?;
+    assert!(res.is_err());
+
+    let revealed = dao.reveal_signs(Scope::guild(9), &no_events).await?;
+    assert_eq!(1, revealed.len());
+    assert!(!revealed[0].hidden);
+
+    let revealed = dao.reveal_signs(Scope::guild(9), &no_events).await?;
+    assert!(revealed.is_empty());
+
+    let res = dao.change_sign_state(Scope::guild(9), g.signs[0].row_id, SignState::Success { by_user_id: 2 }, &no_events).await?;
+    assert!(res.is_ok());
+
+    Ok(())
+}
+
+async fn test_campaigns(dao: &impl Dao) -> Result<()> {
+    let scope = dao.get_channel_scope(10, 100).await?;
+    assert_eq!(Scope::guild(10), scope);
+
+    let c = dao.create_campaign(10, "first".to_string(), Some("pack".to_string())).await?;
+    assert!(c.is_some());
+    let c = c.unwrap();
+
+    let other = dao.create_campaign(10, "first".to_string(), None).await?;
+    assert!(other.is_none());
+
+    let second = dao.create_campaign(10, "second".to_string(), None).await?.unwrap();
+    assert_eq!(Some("pack".to_string()), dao.get_sign_pack(c.scope()).await?);
+    assert_eq!(None, dao.get_sign_pack(second.scope()).await?);
+    assert_eq!(None, dao.get_sign_pack(Scope::guild(10)).await?);
+    assert_eq!(None, dao.get_sign_pack(Scope::campaign(11, c.id)).await?);
+
+    let bound = dao.bind_channel(10, "missing".to_string(), 100).await?;
+    assert!(bound.is_none());
+
+    let bound = dao.bind_channel(10, "first".to_string(), 100).await?;
+    assert_eq!(vec![100], bound.unwrap().channels);
+
+    let scope = dao.get_channel_scope(10, 100).await?;
+    assert_eq!(c.scope(), scope);
+
+    let g = dao.create_sign(scope, "campaign".to_string(), 1).await?;
+    assert!(g.is_some());
+
+    let g = dao.create_sign(Scope::guild(10), "guild".to_string(), 1).await?;
+    assert!(g.is_some());
+
+    assert_eq!("campaign", dao.get_guild_info(scope).await?.unwrap().signs[0].id);
+    assert_eq!("guild", dao.get_guild_info(Scope::guild(10)).await?.unwrap().signs[0].id);
+
+    dao.save_user_info(UserInfo { shaman_power: 15, ..UserInfo::new(1, scope) }).await?;
+    assert!(dao.get_user_info(1, Scope::guild(10)).await?.is_none());
+    assert_eq!(15, dao.get_user_info(1, scope).await?.unwrap().shaman_power);
+
+    dao.save_guild_settings(GuildSettings { assist_bonus: 5, ..GuildSettings::new(Scope::guild(10)) }).await?;
+    let s = dao.get_guild_settings(scope).await?.unwrap();
+    assert_eq!(5, s.assist_bonus);
+    assert_eq!(c.id, s.campaign_id);
+
+    assert!(dao.unbind_channel(10, 100).await?);
+    assert!(!dao.unbind_channel(10, 100).await?);
+    assert_eq!(Scope::guild(10), dao.get_channel_scope(10, 100).await?);
+
+    Ok(())
+}
+
+async fn test_characters(dao: &impl Dao) -> Result<()> {
+    let scope = Scope::guild(11);
+
+    let c = dao.create_character(1, scope, "Шаман".to_string()).await?;
+    assert!(c.is_some());
+    assert_eq!(10, c.unwrap().shaman_power);
+
+    let c = dao.create_character(1, scope, "Шаман".to_string()).await?;
+    assert!(c.is_none());
+
+    assert!(!dao.set_active_character(1, scope, Some("Другой".to_string())).await?);
+    assert!(dao.set_active_character(1, scope, Some("Шаман".to_string())).await?);
+
+    let mut u = dao.get_user_info(1, scope).await?.unwrap();
+    assert_eq!(Some("Шаман".to_string()), u.active_character_name);
+
+    u.shaman_power = 14;
+    dao.save_user_info(u).await?;
+    dao.add_luck_dice(1, scope, 1).await?;
+
+    let characters = dao.get_characters(1, scope).await?;
+    assert_eq!(1, characters.len());
+    assert_eq!(14, characters[0].shaman_power);
+
+    assert!(dao.set_active_character(1, scope, None).await?);
+
+    let u = dao.get_user_info(1, scope).await?.unwrap();
+    assert!(u.active_character_id.is_none());
+    assert_eq!(10, u.shaman_power);
+    assert_eq!(1, u.luck_dice);
+
+    Ok(())
+}
+
+async fn test_power_rules(dao: &impl Dao) -> Result<()> {
+    let scope = Scope::guild(12);
+
+    dao.save_guild_settings(GuildSettings {
+        power_max: 18,
+        power_decay: 2,
+        ranks: "5:Шаман,0:Ученик".parse()?,
+        ..GuildSettings::new(scope)
+    }).await?;
+
+    let s = dao.get_guild_settings(scope).await?.unwrap();
+    assert_eq!(1, s.power_min);
+    assert_eq!(18, s.power_max);
+    assert_eq!(2, s.power_decay);
+    assert_eq!("0:Ученик,5:Шаман", s.ranks.to_string());
+
+    let updated_at = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+
+    dao.save_user_info(UserInfo {
+        shaman_power: 15,
+        power_updated_at: updated_at,
+        ..UserInfo::new(1, scope)
+    }).await?;
+
+    let u = dao.get_user_info(1, scope).await?.unwrap();
+    assert_eq!(15, u.shaman_power);
+    assert!(u.power_updated_at.duration_since(updated_at).unwrap_or_else(|e| e.duration()) < Duration::from_secs(1));
+
+    Ok(())
+}
+
+async fn test_rank_roles(dao: &impl Dao) -> Result<()> {
+    let scope = Scope::guild(13);
+
+    let mut s = GuildSettings::new(scope);
+    assert!(s.rank_roles.is_empty());
+
+    s.rank_roles.set(16, 300);
+    s.rank_roles.set(10, 200);
+    s.rank_roles.set(12, 200);
+    dao.save_guild_settings(s).await?;
+
+    let mut s = dao.get_guild_settings(scope).await?.unwrap();
+    assert_eq!("12:200,16:300", s.rank_roles.to_string());
+    assert_eq!(None, s.rank_roles.get(11));
+    assert_eq!(Some(200), s.rank_roles.get(15));
+    assert_eq!(Some(300), s.rank_roles.get(20));
+
+    assert!(s.rank_roles.remove(300));
+    assert!(!s.rank_roles.remove(300));
+
+    dao.save_user_info(UserInfo { shaman_power: 12, ..UserInfo::new(1, scope) }).await?;
+    dao.save_user_info(UserInfo { shaman_power: 8, ..UserInfo::new(2, scope) }).await?;
+
+    let mut users = dao.get_users(scope).await?;
+    users.sort_by_key(|u| u.id);
+    assert_eq!(vec![(1, 12), (2, 8)], users.iter().map(|u| (u.id, u.shaman_power)).collect::<Vec<_>>());
+    assert!(dao.get_users(Scope::guild(14)).await?.is_empty());
+
+    Ok(())
+}
+
+async fn test_user_stats(dao: &impl Dao) -> Result<()> {
+    let scope = Scope::guild(15);
+
+    let s = dao.get_user_stats(1, scope).await?;
+    assert_eq!(0, s.signs_rolled);
+    assert_eq!(0, s.modify_attempts);
+    assert!(s.average_d20.is_none());
+    assert!(s.peak_power.is_none());
+    assert!(s.most_frequent_sign.is_none());
+
+    dao.create_signs(scope, vec!["a".to_string(), "b".to_string(), "b".to_string()], true, false, 1, &no_events).await?;
+    dao.add_discarded_sign(scope, "a".to_string(), 1).await?;
+    dao.add_discarded_sign(scope, "a".to_string(), 1).await?;
+    dao.create_sign(Scope::guild(16), "a".to_string(), 1).await?;
+
+    let roll = ModifyRoll { user_id: 1, sign_row_id: 1, d20: 5, value: 5, success: false, critical: false, power_before: 10, power_after: 11 };
+    dao.add_modify_roll(scope, roll.clone()).await?;
+    dao.add_modify_roll(scope, ModifyRoll { d20: 20, value: 20, success: true, critical: true, power_before: 11, power_after: 10, ..roll.clone() }).await?;
+    dao.add_modify_roll(scope, ModifyRoll { user_id: 2, power_after: 18, ..roll }).await?;
+
+    let s = dao.get_user_stats(1, scope).await?;
+    assert_eq!(3, s.signs_rolled);
+    assert_eq!(2, s.modify_attempts);
+    assert_eq!(1, s.modify_successes);
+    assert_eq!(Some(12.5), s.average_d20);
+    assert_eq!(Some(11), s.peak_power);
+    assert_eq!(Some(("b".to_string(), 2)), s.most_frequent_sign);
+
+    // Hidden signs are not counted until GM reveals them
+    let hidden = Scope::campaign(15, 1);
+    dao.create_signs(hidden, vec!["c".to_string(), "c".to_string()], true, true, 1, &no_events).await?;
+
+    let s = dao.get_user_stats(1, hidden).await?;
+    assert_eq!(0, s.signs_rolled);
+    assert!(s.most_frequent_sign.is_none());
+
+    Ok(())
+}
+
+async fn test_guild_stats(dao: &impl Dao) -> Result<()> {
+    let scope = Scope::guild(17);
+
+    assert!(dao.get_sign_stats(scope).await?.is_empty());
+    assert_eq!(Streaks::default(), dao.get_streaks(scope).await?);
+
+    let g = dao.create_signs(scope, vec!["a".to_string(), "a".to_string(), "b".to_string(), "a".to_string(), "c".to_string()], true, false, 1, &no_events).await?.unwrap();
+    dao.add_discarded_sign(scope, "c".to_string(), 1).await?;
+
+    let states = [
+        SignState::Success { by_user_id: 2 },
+        SignState::CriticalSuccess { by_user_id: 2 },
+        SignState::Failed { by_user_id: 2 },
+        SignState::CriticalFailure { by_user_id: 2 },
+        SignState::Failed { by_user_id: 2 },
+    ];
+    for (sign, state) in g.signs.iter().zip(states) {
+        assert!(dao.change_sign_state(scope, sign.row_id, state, &no_events).await?.is_ok());
+    }
+
+    let stats = dao.get_sign_stats(scope).await?;
+    assert_eq!(3, stats.len());
+    assert_eq!(("a", 3, 2, 1), (stats[0].sign_id.as_str(), stats[0].rolled, stats[0].successes, stats[0].failures));
+    assert_eq!(("b", 1, 0, 1), (stats[1].sign_id.as_str(), stats[1].rolled, stats[1].successes, stats[1].failures));
+    assert_eq!(("c", 1, 0, 1), (stats[2].sign_id.as_str(), stats[2].rolled, stats[2].successes, stats[2].failures));
+
+    assert_eq!(Streaks { success: 2, failure: 3 }, dao.get_streaks(scope).await?);
+
+    Ok(())
+}
+
+async fn test_achievements(dao: &impl Dao) -> Result<()> {
+    let scope = Scope::guild(18);
+
+    assert_eq!(0, dao.get_success_streak(1, scope).await?);
+
+    let roll = ModifyRoll { user_id: 1, sign_row_id: 1, d20: 15, value: 15, success: true, critical: false, power_before: 10, power_after: 10 };
+    dao.add_modify_roll(scope, roll.clone()).await?;
+    dao.add_modify_roll(scope, ModifyRoll { success: false, ..roll.clone() }).await?;
+    dao.add_modify_roll(scope, roll.clone()).await?;
+    dao.add_modify_roll(scope, roll.clone()).await?;
+    dao.add_modify_roll(scope, ModifyRoll { user_id: 2, success: false, ..roll }).await?;
+    assert_eq!(2, dao.get_success_streak(1, scope).await?);
+
+    let unlocked = dao.unlock_achievements(1, scope, vec!["a".to_string(), "b".to_string()]).await?;
+    assert_eq!(2, unlocked.len());
+
+    let unlocked = dao.unlock_achievements(1, scope, vec!["b".to_string(), "c".to_string()]).await?;
+    assert_eq!(vec!["c".to_string()], unlocked);
+
+    let achievements = dao.get_achievements(1, scope).await?;
+    assert_eq!(3, achievements.len());
+    assert!(dao.get_achievements(2, scope).await?.is_empty());
+
+    Ok(())
+}
+
+async fn test_webhooks(dao: &impl Dao) -> Result<()> {
+    let scope = Scope::guild(19);
+
+    // Events of scope without webhooks are dropped
+    let pack = signs::load_pack("enoa_03_sign_pack.json")?;
+
+    let g = dao.create_signs(scope, vec!["1111".to_string()], true, false, 1, &webhooks::sign_rolled(scope, &pack)).await?.unwrap();
+    assert!(dao.get_due_webhook_events(10).await?.is_empty());
+
+    let w = dao.create_webhook(scope, "https://example.com/a".to_string(), "secret".to_string()).await?;
+    assert!(w.is_some());
+    assert!(dao.create_webhook(scope, "https://example.com/a".to_string(), "other".to_string()).await?.is_none());
+    let w2 = dao.create_webhook(scope, "https://example.com/b".to_string(), "secret".to_string()).await?.unwrap();
+    assert_eq!(2, dao.get_webhooks(scope).await?.len());
+
+    let roll = ModifyRoll { user_id: 2, sign_row_id: g.signs[0].row_id, d20: 15, value: 15, success: true, critical: false, power_before: 10, power_after: 9 };
+    let modified = webhooks::sign_modified(scope, &pack, roll);
+    assert!(dao.change_sign_state(scope, g.signs[0].row_id, SignState::Success { by_user_id: 2 }, &modified).await?.is_ok());
+
+    // Events are not saved when sign is not changed
+    assert!(dao.change_sign_state(scope, g.signs[0].row_id, SignState::Failed { by_user_id: 2 }, &modified).await?.is_err());
+
+    let events = dao.get_due_webhook_events(10).await?;
+    assert_eq!(2, events.len());
+    assert_eq!("sign.modified", events[0].event_type);
+    assert!(events[0].payload.contains("\"success\":true"));
+    assert_eq!(0, events[0].attempts);
+
+    dao.mark_webhook_delivered(events[0].id).await?;
+    dao.mark_webhook_failed(events[1].id, "timeout".to_string(), Some(Duration::from_secs(3600))).await?;
+    assert!(dao.get_due_webhook_events(10).await?.is_empty());
+
+    dao.mark_webhook_failed(events[1].id, "timeout".to_string(), Some(Duration::ZERO)).await?;
+    let events = dao.get_due_webhook_events(10).await?;
+    assert_eq!(1, events.len());
+    assert_eq!(2, events[0].attempts);
+
+    assert!(dao.delete_webhook(scope, w2.id).await?);
+    assert!(!dao.delete_webhook(scope, w2.id).await?);
+    assert!(dao.get_due_webhook_events(10).await?.is_empty());
+
+    // Signs are not saved when their events cannot be built
+    let other = Scope::campaign(19, 1);
+    assert!(dao.create_signs(other, vec!["a".to_string()], true, false, 1, &|_| Err(anyhow!("No payload"))).await.is_err());
+    assert!(dao.get_guild_info(other).await?.is_none());
+
+    Ok(())
+}
+
+async fn test_api_tokens(dao: &impl Dao) -> Result<()> {
+    let guild_id = 20;
+    let hash = api::hash_token("token");
+
+    assert!(!dao.check_api_token(guild_id, hash.clone()).await?);
+
+    let token = dao.create_api_token(guild_id, "overlay".to_string(), hash.clone(), 1).await?.unwrap();
+    assert_eq!("overlay", token.name);
+    assert_eq!(1, token.created_by_user_id);
+    assert!(dao.create_api_token(guild_id, "overlay".to_string(), api::hash_token("other"), 1).await?.is_none());
+
+    assert!(dao.check_api_token(guild_id, hash.clone()).await?);
+    assert!(!dao.check_api_token(guild_id + 1, hash.clone()).await?);
+    assert_eq!(1, dao.get_api_tokens(guild_id).await?.len());
+
+    assert!(dao.delete_api_token(guild_id, "overlay".to_string()).await?);
+    assert!(!dao.delete_api_token(guild_id, "overlay".to_string()).await?);
+    assert!(!dao.check_api_token(guild_id, hash).await?);
+
+    Ok(())
+}
+
+async fn test_guild_setup(dao: &impl Dao, pool: &Pool) -> Result<()> {
+    let scope = Scope::guild(21);
+
+    assert!(dao.is_valid_timezone("Europe/Moscow".to_string()).await?);
+    assert!(!dao.is_valid_timezone("Mars/Olympus".to_string()).await?);
+
+    let choices = GuildChoices {
+        channels: vec![(7, "signs".to_string())],
+        roles: vec![(5, "GM".to_string()), (6, "Player".to_string())]
+    };
+
+    let settings = sign_setup::apply(dao, 21, &choices, Setup {
+        sign_pack: None,
+        channel_id: Some(7),
+        timezone: Some("Asia/Tokyo".to_string()),
+        cadence: Some(Cadence::Daily),
+        gm_role_id: Some(5)
+    }).await?.unwrap();
+    assert_eq!(Some(7), settings.auto_roll_channel_id);
+
+    let s = dao.get_guild_settings(scope).await?.unwrap();
+    assert_eq!(Some("Asia/Tokyo".to_string()), s.timezone);
+    assert_eq!(Some(5), s.gm_role_id);
+
+    // Invalid choice keeps settings as they are
+    let res = sign_setup::apply(dao, 21, &choices, Setup { timezone: Some("Mars/Olympus".to_string()), gm_role_id: Some(6), ..Default::default() }).await?;
+    assert!(res.is_err());
+    assert_eq!(Some(5), dao.get_guild_settings(scope).await?.unwrap().gm_role_id);
+
+    // Only channels and roles of guild can be chosen
+    let res = sign_setup::apply(dao, 21, &choices, Setup { channel_id: Some(8), gm_role_id: Some(6), ..Default::default() }).await?;
+    assert!(res.is_err());
+    let res = sign_setup::apply(dao, 21, &choices, Setup { gm_role_id: Some(9), ..Default::default() }).await?;
+    assert!(res.is_err());
+    assert_eq!(Some(7), dao.get_guild_settings(scope).await?.unwrap().auto_roll_channel_id);
+    assert_eq!(Some(5), dao.get_guild_settings(scope).await?.unwrap().gm_role_id);
+
+    // Only loaded packs can be chosen
+    let res = sign_setup::apply(dao, 21, &choices, Setup { sign_pack: Some("missing".to_string()), ..Default::default() }).await?;
+    assert!(res.is_err());
+    assert!(dao.get_sign_pack(scope).await?.is_none());
+
+    // Campaign without own pack uses pack of guild
+    dao.save_guild_settings(GuildSettings { sign_pack: Some("guild".to_string()), ..dao.get_guild_settings(scope).await?.unwrap() }).await?;
+    let own = dao.create_campaign(21, "own".to_string(), Some("campaign".to_string())).await?.unwrap();
+    let shared = dao.create_campaign(21, "shared".to_string(), None).await?.unwrap();
+    assert_eq!(Some("guild".to_string()), dao.get_sign_pack(scope).await?);
+    assert_eq!(Some("campaign".to_string()), dao.get_sign_pack(own.scope()).await?);
+    assert_eq!(Some("guild".to_string()), dao.get_sign_pack(shared.scope()).await?);
+
+    // Day of daily cadence starts in timezone of guild
+    dao.create_signs(scope, vec!["1234".to_string()], true, false, 1, &no_events).await?.unwrap();
+    assert!(dao.get_current_session(scope).await?.is_some());
+    assert_eq!(1, dao.get_guild_info(scope).await?.unwrap().signs.len());
+
+    let client = pool.get().await?;
+    let tokyo_midnight = "((NOW() AT TIME ZONE 'Asia/Tokyo')::date::timestamp AT TIME ZONE 'Asia/Tokyo')::timestamp";
+
+    client.execute(&format!("UPDATE sessions SET started_at = {} - interval '1 second' WHERE guild_id = '21'", tokyo_midnight), &[]).await?;
+    assert!(dao.get_current_session(scope).await?.is_none());
+    assert!(dao.get_guild_info(scope).await?.is_none());
+
+    client.execute(&format!("UPDATE sessions SET started_at = {} WHERE guild_id = '21'", tokyo_midnight), &[]).await?;
+    assert!(dao.get_current_session(scope).await?.is_some());
+    assert_eq!(1, dao.get_guild_info(scope).await?.unwrap().signs.len());
+
+    // Campaign with own settings gets setup choices of guild
+    dao.save_guild_settings(GuildSettings { assist_bonus: 3, ..dao.get_guild_settings(own.scope()).await?.unwrap() }).await?;
+    sign_setup::apply(dao, 21, &choices, Setup { timezone: Some("Europe/Moscow".to_string()), gm_role_id: Some(6), ..Default::default() }).await?.unwrap();
+
+    let s = dao.get_guild_settings(own.scope()).await?.unwrap();
+    assert_eq!(3, s.assist_bonus);
+    assert_eq!(own.id, s.campaign_id);
+    assert_eq!(Some("Europe/Moscow".to_string()), s.timezone);
+    assert_eq!(Some(6), s.gm_role_id);
+    assert_eq!(Some("guild".to_string()), s.sign_pack);
+    assert_eq!(1, dao.get_guild_settings(scope).await?.unwrap().assist_bonus);
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use crate::dice::{D20Roll, RollMode};
+
+#[test]
+fn test_combine() {
+    assert_eq!(RollMode::Normal, RollMode::combine(&[]));
+    assert_eq!(RollMode::Normal, RollMode::combine(&[RollMode::Normal, RollMode::Normal]));
+    assert_eq!(RollMode::Advantage, RollMode::combine(&[RollMode::Normal, RollMode::Advantage]));
+    assert_eq!(RollMode::Advantage, RollMode::combine(&[RollMode::Advantage, RollMode::Advantage]));
+    assert_eq!(RollMode::Disadvantage, RollMode::combine(&[RollMode::Disadvantage, RollMode::Normal]));
+    assert_eq!(RollMode::Normal, RollMode::combine(&[RollMode::Advantage, RollMode::Disadvantage]));
+    assert_eq!(RollMode::Normal, RollMode::combine(&[RollMode::Advantage, RollMode::Advantage, RollMode::Disadvantage]));
+}
+
+#[test]
+fn test_roll() {
+    for _ in 0..100 {
+        let d20 = D20Roll::roll(RollMode::Normal);
+        assert_eq!(1, d20.dice.len());
+        assert_eq!(d20.dice[0], d20.result);
+        assert!((1..=20).contains(&d20.result));
+
+        let d20 = D20Roll::roll(RollMode::Advantage);
+        assert_eq!(2, d20.dice.len());
+        assert_eq!(*d20.dice.iter().max().unwrap(), d20.result);
+        assert!((1..=20).contains(&d20.result));
+
+        let d20 = D20Roll::roll(RollMode::Disadvantage);
+        assert_eq!(2, d20.dice.len());
+        assert_eq!(*d20.dice.iter().min().unwrap(), d20.result);
+        assert!((1..=20).contains(&d20.result));
+    }
+}
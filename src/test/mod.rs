@@ -1 +1,6 @@
-mod dao_test;
\ No newline at end of file
+mod dao_test;
+mod dice_test;
+mod oauth_test;
+mod power_test;
+mod signs_test;
+mod webhooks_test;
\ No newline at end of file
@@ -0,0 +1,93 @@
+use std::{convert::Infallible, time::{Duration, SystemTime}};
+
+use anyhow::Result;
+use http_body_util::{BodyExt, Full};
+use hyper::{body::{Bytes, Incoming}, server::conn::http1, service::service_fn, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+
+use crate::oauth::{self, OAuthConf};
+
+#[test]
+fn test_state() {
+    let now = SystemTime::now();
+    let state = oauth::make_state("secret", "nonce", now);
+
+    assert!(oauth::verify_state("secret", &state, "nonce", now));
+    assert!(oauth::verify_state("secret", &state, "nonce", now + oauth::STATE_TTL - Duration::from_secs(1)));
+
+    // Other browser, expired, other key or tampered state
+    assert!(!oauth::verify_state("secret", &state, "other", now));
+    assert!(!oauth::verify_state("secret", &state, "nonce", now + oauth::STATE_TTL + Duration::from_secs(1)));
+    assert!(!oauth::verify_state("other", &state, "nonce", now));
+    assert!(!oauth::verify_state("secret", &state.replacen("nonce", "other", 1), "other", now));
+    assert!(!oauth::verify_state("secret", "garbage", "nonce", now));
+}
+
+/**
+ * Local stub of Discord token endpoint, answers once with given status and body
+ */
+async fn start_stub(status: StatusCode, body: &'static str) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+
+        let service = service_fn(move |req: Request<Incoming>| async move {
+            assert_eq!("/api/oauth2/token", req.uri().path());
+            assert!(req.headers().contains_key("Authorization"));
+
+            let form = req.collect().await.unwrap().to_bytes();
+            let form = String::from_utf8(form.to_vec()).unwrap();
+            assert!(form.contains("grant_type=authorization_code"));
+            assert!(form.contains("code=code"));
+
+            Ok::<_, Infallible>(Response::builder()
+                .status(status)
+                .body(Full::new(Bytes::from(body)))
+                .unwrap())
+        });
+
+        http1::Builder::new().serve_connection(TokioIo::new(stream), service).await.unwrap();
+    });
+
+    Ok(format!("http://{}/api", addr))
+}
+
+fn conf(api_url: String) -> OAuthConf {
+    OAuthConf {
+        api_url,
+        client_id: 1,
+        client_secret: "client_secret".to_string(),
+        redirect_url: "https://example.com/oauth".to_string(),
+        secret: "secret".to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_exchange_code() -> Result<()> {
+    let client = reqwest::Client::new();
+
+    let api_url = start_stub(StatusCode::OK, r#"{"access_token":"token","guild":{"id":"42","name":"Guild"}}"#).await?;
+    assert_eq!(42, oauth::exchange_code(&client, &conf(api_url), "code").await?);
+
+    let api_url = start_stub(StatusCode::OK, r#"{"access_token":"token"}"#).await?;
+    assert!(oauth::exchange_code(&client, &conf(api_url), "code").await.is_err());
+
+    let api_url = start_stub(StatusCode::BAD_REQUEST, r#"{"error":"invalid_grant"}"#).await?;
+    assert!(oauth::exchange_code(&client, &conf(api_url), "code").await.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_authorize_url() -> Result<()> {
+    let url = oauth::authorize_url(&conf(oauth::DEFAULT_API_URL.to_string()), "state")?;
+
+    assert!(url.starts_with("https://discord.com/oauth2/authorize?client_id=1&"));
+    assert!(url.contains("redirect_uri=https%3A%2F%2Fexample.com%2Foauth"));
+    assert!(url.contains("state=state"));
+
+    Ok(())
+}
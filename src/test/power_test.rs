@@ -0,0 +1,66 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::{db::{GuildSettings, Scope, UserInfo}, power::{self, Ranks}};
+
+fn settings() -> Result<GuildSettings> {
+    Ok(GuildSettings {
+        power_max: 18,
+        power_decay: 2,
+        ranks: "5:Шаман,0:Ученик".parse()?,
+        ..GuildSettings::new(Scope::guild(1))
+    })
+}
+
+#[test]
+fn test_ranks() -> Result<()> {
+    let ranks: Ranks = "5:Шаман,0:Ученик".parse()?;
+
+    assert_eq!("0:Ученик,5:Шаман", ranks.to_string());
+    assert_eq!("Ученик", ranks.get(4).unwrap().title);
+    assert_eq!("Шаман", ranks.get(5).unwrap().title);
+    assert!(ranks.get(-1).is_none());
+
+    assert!("Шаман".parse::<Ranks>().is_err());
+    assert!("x:Шаман".parse::<Ranks>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_render_power() -> Result<()> {
+    let s = settings()?;
+
+    assert_eq!("20 (Шаман)", power::render_power(20, &s));
+    assert_eq!("-1", power::render_power(-1, &s));
+    assert_eq!(18, power::clamp(20, &s));
+    assert_eq!(1, power::clamp(-1, &s));
+
+    Ok(())
+}
+
+#[test]
+fn test_regress() -> Result<()> {
+    let s = settings()?;
+    let now = SystemTime::now();
+    let day = Duration::from_secs(24 * 60 * 60);
+
+    let mut u = UserInfo {
+        shaman_power: 15,
+        power_updated_at: now - day * 2 - day / 2,
+        ..UserInfo::new(1, Scope::guild(1))
+    };
+
+    power::regress(&mut u, &s, now);
+    assert_eq!(11, u.shaman_power);
+
+    // Half of the day left from previous regression is kept
+    power::regress(&mut u, &s, now + day * 3 / 4);
+    assert_eq!(10, u.shaman_power);
+
+    power::regress(&mut u, &s, now + day * 5);
+    assert_eq!(10, u.shaman_power);
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use crate::signs;
+
+#[test]
+fn test_sign_probability() {
+    assert_eq!(1.0 / 256.0, signs::sign_probability("1111"));
+    assert_eq!(24.0 / 256.0, signs::sign_probability("1234"));
+    assert_eq!(6.0 / 256.0, signs::sign_probability("1122"));
+    assert_eq!(12.0 / 256.0, signs::sign_probability("1123"));
+}
+
+#[test]
+fn test_load_pack() {
+    let pack = signs::load_pack("enoa_03_sign_pack.json").unwrap();
+    assert_eq!("enoa_03_sign_pack", pack.name);
+
+    let list = pack.list_signs();
+    assert_eq!(35, list.len());
+    assert_eq!("1111", list[0].id);
+    assert_eq!("4444", list[34].id);
+    assert!(pack.get_sign("5555").is_none());
+}
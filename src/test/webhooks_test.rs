@@ -0,0 +1,9 @@
+use crate::webhooks;
+
+#[test]
+fn test_signature() {
+    assert_eq!(
+        "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+        webhooks::signature("key", "The quick brown fox jumps over the lazy dog")
+    );
+}
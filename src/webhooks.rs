@@ -0,0 +1,138 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{api::SignView, db::{Dao, ModifyRoll, OutboxEvent, Scope, SignInfo, WebhookEvent}, signs::Pack};
+
+/// Increased on incompatible payload changes
+const PAYLOAD_VERSION: u32 = 1;
+const BATCH_SIZE: i64 = 50;
+const MAX_ATTEMPTS: i32 = 10;
+const FIRST_RETRY: Duration = Duration::from_secs(10);
+
+pub const SIGN_ROLLED: &str = "sign.rolled";
+pub const SIGN_MODIFIED: &str = "sign.modified";
+pub const SIGN_REVEALED: &str = "sign.revealed";
+
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    version: u32,
+    event: &'a str,
+    guild_id: String,
+    campaign_id: i64,
+    timestamp: u64,
+    sign: SignView,
+    roll: Option<RollPayload>
+}
+
+#[derive(Debug, Serialize)]
+struct RollPayload {
+    user_id: String,
+    d20: i32,
+    value: i32,
+    success: bool,
+    critical: bool,
+    power_before: i32,
+    power_after: i32
+}
+
+/**
+ * Events about rolled signs, hidden ones are sent when revealed
+ * Events are saved to outbox with signs and delivered by background task
+ */
+pub fn sign_rolled(scope: Scope, pack: &Pack) -> impl Fn(&[SignInfo]) -> Result<Vec<WebhookEvent>> + Send + Sync + '_ {
+    move |signs| signs.iter()
+        .filter(|s| !s.hidden)
+        .map(|s| event(scope, pack, SIGN_ROLLED, s, None))
+        .collect()
+}
+
+pub fn sign_modified(scope: Scope, pack: &Pack, roll: ModifyRoll) -> impl Fn(&[SignInfo]) -> Result<Vec<WebhookEvent>> + Send + Sync + '_ {
+    move |signs| signs.iter()
+        .map(|s| event(scope, pack, SIGN_MODIFIED, s, Some(&roll)))
+        .collect()
+}
+
+pub fn signs_revealed(scope: Scope, pack: &Pack) -> impl Fn(&[SignInfo]) -> Result<Vec<WebhookEvent>> + Send + Sync + '_ {
+    move |signs| signs.iter()
+        .map(|s| event(scope, pack, SIGN_REVEALED, s, None))
+        .collect()
+}
+
+fn event(scope: Scope, pack: &Pack, event: &str, sign: &SignInfo, roll: Option<&ModifyRoll>) -> Result<WebhookEvent> {
+    let payload = Payload {
+        version: PAYLOAD_VERSION,
+        event,
+        guild_id: scope.guild_id.to_string(),
+        campaign_id: scope.campaign_id,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        sign: SignView::new(pack, sign),
+        roll: roll.map(|r| RollPayload {
+            user_id: r.user_id.to_string(),
+            d20: r.d20,
+            value: r.value,
+            success: r.success,
+            critical: r.critical,
+            power_before: r.power_before,
+            power_after: r.power_after
+        })
+    };
+
+    Ok(WebhookEvent {
+        event_type: event.to_string(),
+        payload: serde_json::to_string(&payload)?
+    })
+}
+
+/**
+ * Hex HMAC-SHA256 of payload, sent in `X-Sign-Signature` header as `sha256={signature}`
+ */
+pub fn signature(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts key of any size");
+    mac.update(payload.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/**
+ * Sends due events from outbox, failed ones are retried with exponential backoff
+ */
+pub async fn deliver_due(dao: &dyn Dao, client: &reqwest::Client) -> Result<()> {
+    let events = dao.get_due_webhook_events(BATCH_SIZE).await?;
+
+    for event in events {
+        match deliver(client, &event).await {
+            Ok(()) => dao.mark_webhook_delivered(event.id).await?,
+            Err(e) => {
+                let retry_in = (event.attempts + 1 < MAX_ATTEMPTS)
+                    .then(|| FIRST_RETRY * 2u32.pow(event.attempts as u32));
+
+                warn!("Cannot deliver webhook event {} to {}, retry in {:?}: {}", event.id, event.url, retry_in, e);
+                dao.mark_webhook_failed(event.id, e.to_string(), retry_in).await?;
+            },
+        }
+    }
+
+    Ok(())
+}
+
+async fn deliver(client: &reqwest::Client, event: &OutboxEvent) -> Result<()> {
+    let res = client.post(&event.url)
+        .header("Content-Type", "application/json")
+        .header("X-Sign-Event", &event.event_type)
+        .header("X-Sign-Delivery", event.id.to_string())
+        .header("X-Sign-Signature", format!("sha256={}", signature(&event.secret, &event.payload)))
+        .body(event.payload.clone())
+        .send()
+        .await?;
+
+    res.error_for_status()?;
+    info!("Webhook event {} delivered to {}", event.id, event.url);
+
+    Ok(())
+}